@@ -0,0 +1,252 @@
+//the "securePassthrough" transport tapo devices used before klap.
+//an rsa handshake hands us an aes key, after which every request is
+//aes-128-cbc encrypted, base64'd and wrapped in a securePassthrough call.
+// https://github.com/python-kasa/python-kasa/blob/master/kasa/transports/aestransport.py
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use rand_core::OsRng;
+use rsa::pkcs8::{EncodePublicKey, LineEnding};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, RsaPublicKey};
+use serde_json::{json, Value};
+use sha1::{Digest, Sha1};
+
+use crate::credentials::Credentials;
+use crate::error::{KasaError, Result};
+use crate::http::{self, HttpResponse};
+use crate::smart_protocol::{check_smart_resp, smart_request};
+use crate::transport::{ConnectionConfig, Transport};
+
+pub const AES_PORT: u16 = 80;
+//returned by login_device when the device wants the sha1'd password instead
+const LOGIN_V2_REQUIRED: i64 = -1501;
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+fn sha1_hex(input: &str) -> String {
+    Sha1::digest(input.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+struct AesSession {
+    key: [u8; 16],
+    iv: [u8; 16],
+    cookie: String,
+    token: Option<String>,
+}
+
+impl AesSession {
+    fn encrypt(&self, request: &str) -> String {
+        let ciphertext = Aes128CbcEnc::new(&self.key.into(), &self.iv.into())
+            .encrypt_padded_vec_mut::<Pkcs7>(request.as_bytes());
+        STANDARD.encode(ciphertext)
+    }
+
+    fn decrypt(&self, response: &str) -> Result<String> {
+        let ciphertext = STANDARD
+            .decode(response)
+            .map_err(|err| KasaError::Crypto(format!("response is not base64: {err}")))?;
+        let plaintext = Aes128CbcDec::new(&self.key.into(), &self.iv.into())
+            .decrypt_padded_vec_mut::<Pkcs7>(&ciphertext)
+            .map_err(|_| KasaError::Crypto("bad padding in response".to_string()))?;
+        Ok(String::from_utf8_lossy(&plaintext).into_owned())
+    }
+}
+
+pub struct AesTransport {
+    addr: String,
+    credentials: Credentials,
+    config: ConnectionConfig,
+    session: Option<AesSession>,
+    response: Option<String>,
+}
+
+impl AesTransport {
+    //addr is "ip" for a real device, or "ip:port" to point at something else
+    pub fn new(addr: &str, credentials: Credentials, config: ConnectionConfig) -> AesTransport {
+        AesTransport {
+            addr: addr.to_string(),
+            credentials,
+            config,
+            session: None,
+            response: None,
+        }
+    }
+
+    fn post(&self, path: &str, body: &Value) -> Result<Value> {
+        let cookie = self
+            .session
+            .as_ref()
+            .map(|session| format!("TP_SESSIONID={}", session.cookie));
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(cookie) = cookie.as_deref() {
+            headers.push(("Cookie", cookie));
+        }
+        let body = body.to_string();
+        let resp = http::post(
+            &self.addr,
+            AES_PORT,
+            path,
+            &headers,
+            body.as_bytes(),
+            &self.config,
+        )?;
+        check_status(&resp)?;
+        Ok(serde_json::from_slice(&resp.body)?)
+    }
+
+    fn passthrough(&self, request: &str) -> Result<String> {
+        let session = self
+            .session
+            .as_ref()
+            .ok_or_else(|| KasaError::Authentication("no aes session".to_string()))?;
+        let path = match &session.token {
+            Some(token) => format!("/app?token={token}"),
+            None => "/app".to_string(),
+        };
+        let body = smart_request(
+            "securePassthrough",
+            Some(json!({ "request": session.encrypt(request) })),
+        );
+        let result = check_smart_resp(self.post(&path, &body)?)?;
+        let response = result
+            .get("response")
+            .and_then(Value::as_str)
+            .ok_or_else(|| KasaError::MissingField("result.response".to_string()))?;
+        session.decrypt(response)
+    }
+
+    pub fn handshake(&mut self) -> Result<()> {
+        self.session = None;
+        let private_key = RsaPrivateKey::new(&mut OsRng, 1024)
+            .map_err(|err| KasaError::Crypto(format!("rsa keygen failed: {err}")))?;
+        let public_pem = RsaPublicKey::from(&private_key)
+            .to_public_key_pem(LineEnding::LF)
+            .map_err(|err| KasaError::Crypto(format!("pem encoding failed: {err}")))?;
+
+        let body = smart_request("handshake", Some(json!({ "key": public_pem })));
+        let headers = [("Content-Type", "application/json")];
+        let resp = http::post(
+            &self.addr,
+            AES_PORT,
+            "/app",
+            &headers,
+            body.to_string().as_bytes(),
+            &self.config,
+        )?;
+        check_status(&resp)?;
+        let cookie = resp
+            .cookie("TP_SESSIONID")
+            .ok_or_else(|| KasaError::Http("handshake set no session cookie".to_string()))?
+            .to_string();
+
+        let result = check_smart_resp(serde_json::from_slice(&resp.body)?)?;
+        let key = result
+            .get("key")
+            .and_then(Value::as_str)
+            .ok_or_else(|| KasaError::MissingField("result.key".to_string()))?;
+        let key = STANDARD
+            .decode(key)
+            .map_err(|err| KasaError::Crypto(format!("handshake key is not base64: {err}")))?;
+        let key = private_key
+            .decrypt(Pkcs1v15Encrypt, &key)
+            .map_err(|err| KasaError::Crypto(format!("handshake key rsa failed: {err}")))?;
+        if key.len() < 32 {
+            return Err(KasaError::Crypto(format!(
+                "handshake key is {} bytes, expected 32",
+                key.len()
+            )));
+        }
+
+        let mut session = AesSession {
+            key: [0u8; 16],
+            iv: [0u8; 16],
+            cookie,
+            token: None,
+        };
+        session.key.copy_from_slice(&key[..16]);
+        session.iv.copy_from_slice(&key[16..32]);
+        self.session = Some(session);
+        self.login()
+    }
+
+    fn login(&mut self) -> Result<()> {
+        let username = STANDARD.encode(sha1_hex(&self.credentials.username));
+        let password = STANDARD.encode(&self.credentials.password);
+        let hashed = STANDARD.encode(sha1_hex(&self.credentials.password));
+
+        let v1 = json!({ "username": username, "password": password });
+        let v2 = json!({ "username": username, "password2": hashed });
+        let mut result = self.login_with(v1);
+        if let Err(KasaError::Device {
+            err_code: LOGIN_V2_REQUIRED,
+            ..
+        }) = result
+        {
+            result = self.login_with(v2);
+        }
+        let token = result.map_err(|err| KasaError::Authentication(err.to_string()))?;
+
+        if let Some(session) = self.session.as_mut() {
+            session.token = Some(token);
+        }
+        Ok(())
+    }
+
+    fn login_with(&self, params: Value) -> Result<String> {
+        let request = smart_request("login_device", Some(params)).to_string();
+        let result = check_smart_resp(serde_json::from_str(&self.passthrough(&request)?)?)?;
+        result
+            .get("token")
+            .and_then(Value::as_str)
+            .map(String::from)
+            .ok_or_else(|| KasaError::MissingField("result.token".to_string()))
+    }
+}
+
+fn check_status(resp: &HttpResponse) -> Result<()> {
+    match resp.status {
+        200 => Ok(()),
+        status => Err(KasaError::Http(format!("device returned {status}"))),
+    }
+}
+
+impl Transport for AesTransport {
+    fn send(&mut self, request: &str) -> Result<()> {
+        if !self.is_connected() {
+            self.handshake()?;
+        }
+        match self.passthrough(request) {
+            Ok(response) => {
+                self.response = Some(response);
+                Ok(())
+            }
+            Err(err) => {
+                //expired sessions show up as errors from the passthrough itself,
+                //so start over on the next call
+                self.disconnect();
+                Err(err)
+            }
+        }
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        self.response
+            .take()
+            .ok_or_else(|| KasaError::MissingField("aes response".to_string()))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| session.token.is_some())
+    }
+
+    fn disconnect(&mut self) {
+        self.session = None;
+    }
+}
@@ -0,0 +1,221 @@
+use serde_json::json;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::{Mutex, MutexGuard};
+use tokio::time::{timeout_at, Instant};
+
+use crate::async_kasa_protocol::{
+    self, get_sys_info, set_relay_by_idx, set_single_relay_outlet, toggle_relay_by_idx,
+    toggle_single_relay_outlet, AsyncTcpTransport,
+};
+use crate::command::{Batch, BatchResponse, Command};
+use crate::error::{KasaError, Result};
+use crate::kasa_protocol::{decrypt, deserialize, encrypt};
+use crate::models::{CloudInfo, CnCloud, KasaChildren, KasaResp, Realtime, SysInfo, System};
+use crate::transport::ConnectionConfig;
+use crate::validate_ip;
+
+//async mirror of device::Device, clones share one lazily opened connection
+#[derive(Clone)]
+pub struct AsyncDevice {
+    pub ip_addr: String,
+    pub kasa_info: KasaResp,
+    pub realtime: Vec<Realtime>,
+    config: ConnectionConfig,
+    connection: Arc<Mutex<AsyncTcpTransport>>,
+}
+
+impl AsyncDevice {
+    pub fn new(ip_addr: String, kasa_info: KasaResp) -> AsyncDevice {
+        let config = ConnectionConfig::default();
+        AsyncDevice {
+            connection: Arc::new(Mutex::new(AsyncTcpTransport::new(&ip_addr, config))),
+            ip_addr,
+            kasa_info,
+            realtime: vec![],
+            config,
+        }
+    }
+
+    //replaces the connection, so any open socket is dropped
+    pub fn with_config(mut self, config: ConnectionConfig) -> AsyncDevice {
+        self.config = config;
+        self.connection = Arc::new(Mutex::new(AsyncTcpTransport::new(&self.ip_addr, config)));
+        self
+    }
+
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    async fn transport(&self) -> MutexGuard<'_, AsyncTcpTransport> {
+        self.connection.lock().await
+    }
+
+    pub async fn is_connected(&self) -> bool {
+        self.transport().await.is_connected()
+    }
+
+    pub async fn disconnect(&self) {
+        self.transport().await.disconnect();
+    }
+
+    pub async fn get_children(&self) -> Result<Vec<KasaChildren>> {
+        async_kasa_protocol::get_children(&mut *self.transport().await).await
+    }
+
+    pub async fn get_realtime(&self) -> Result<Vec<Realtime>> {
+        async_kasa_protocol::get_all_realtime(&mut *self.transport().await).await
+    }
+
+    pub async fn execute_batch(&self, batch: &Batch) -> Result<BatchResponse> {
+        async_kasa_protocol::execute_batch(&mut *self.transport().await, batch).await
+    }
+
+    //sysinfo, realtime and the cloud binding in one round trip, see Device::refresh
+    pub async fn refresh(&mut self) -> Result<()> {
+        let sysinfo = Command::get_sysinfo();
+        let realtime = Command::get_realtime();
+        let cloud = Command::get_cloud_info();
+        let resp = self
+            .execute_batch(
+                &Batch::new()
+                    .with(sysinfo.clone())
+                    .with(realtime.clone())
+                    .with(cloud.clone()),
+            )
+            .await?;
+        self.kasa_info.system = Some(System {
+            get_sysinfo: Some(resp.get(&sysinfo)?),
+        });
+        self.realtime = resp.get(&realtime).map(|rt| vec![rt]).unwrap_or_default();
+        self.kasa_info.cloud = Some(CnCloud {
+            get_info: resp.get(&cloud).ok(),
+        });
+        Ok(())
+    }
+
+    pub fn sysinfo(&self) -> Option<SysInfo> {
+        self.kasa_info.system.clone()?.get_sysinfo
+    }
+
+    //the cloud binding as of the last refresh
+    pub fn cloud_info(&self) -> Option<CloudInfo> {
+        self.kasa_info.cloud.clone()?.get_info
+    }
+
+    pub fn children(&self) -> Option<Vec<KasaChildren>> {
+        Some(self.sysinfo()?.children)
+    }
+
+    pub fn num_children(&self) -> Option<usize> {
+        Some(self.sysinfo()?.child_num)
+    }
+
+    pub fn has_children(&self) -> bool {
+        matches!(self.num_children(), Some(nc) if nc > 0)
+    }
+
+    pub async fn toggle_relay_by_id(&self, idx: usize) -> Result<()> {
+        toggle_relay_by_idx(&mut *self.transport().await, idx).await
+    }
+
+    pub async fn set_child_relay_by_idx(&self, idx: usize, state: u8) -> Result<()> {
+        set_relay_by_idx(&mut *self.transport().await, idx, state).await
+    }
+
+    pub async fn set_single_relay(&self, state: u8) -> Result<()> {
+        set_single_relay_outlet(&mut *self.transport().await, state).await
+    }
+
+    pub async fn toggle_single_relay(&self) -> Result<()> {
+        toggle_single_relay_outlet(&mut *self.transport().await).await
+    }
+
+    pub async fn reboot(&self, delay: u32) -> Result<()> {
+        async_kasa_protocol::reboot(&mut *self.transport().await, delay).await
+    }
+
+    pub async fn factory_reset(&self, delay: u32) -> Result<()> {
+        async_kasa_protocol::factory_reset(&mut *self.transport().await, delay).await
+    }
+
+    pub async fn set_led_off(&self, off: bool) -> Result<()> {
+        async_kasa_protocol::set_led_off(&mut *self.transport().await, off).await
+    }
+
+    pub async fn set_location(&self, latitude: f64, longitude: f64) -> Result<()> {
+        async_kasa_protocol::set_dev_location(&mut *self.transport().await, latitude, longitude)
+            .await
+    }
+
+    pub async fn set_alias(&self, alias: &str) -> Result<()> {
+        async_kasa_protocol::set_dev_alias(&mut *self.transport().await, alias).await
+    }
+
+    pub async fn set_child_alias(&self, child_id: &str, alias: &str) -> Result<()> {
+        async_kasa_protocol::set_outlet_alias(&mut *self.transport().await, child_id, alias).await
+    }
+}
+
+pub async fn determine_target(t_addr: String) -> Result<AsyncDevice> {
+    if !validate_ip(&t_addr) {
+        return Err(KasaError::InvalidAddress(t_addr));
+    }
+    let si = get_sys_info(&mut AsyncTcpTransport::new(
+        &t_addr,
+        ConnectionConfig::default(),
+    ))
+    .await?;
+    Ok(AsyncDevice::new(
+        t_addr,
+        KasaResp {
+            system: Some(System {
+                get_sysinfo: Some(si),
+            }),
+            emeter: None,
+            cloud: None,
+        },
+    ))
+}
+
+//yields devices one at a time as their replies to the broadcast come in,
+//finishing once `wait` has passed since the broadcast went out
+pub struct Discovery {
+    socket: UdpSocket,
+    deadline: Instant,
+    buf: Vec<u8>,
+}
+
+impl Discovery {
+    pub async fn next(&mut self) -> Option<AsyncDevice> {
+        loop {
+            let (amt, addr) =
+                match timeout_at(self.deadline, self.socket.recv_from(&mut self.buf)).await {
+                    Ok(Ok(recv)) => recv,
+                    Ok(Err(_)) | Err(_) => return None,
+                };
+            //a garbled reply from one device shouldn't sink the whole scan
+            if let Ok(info) = deserialize(&decrypt(&self.buf[..amt])) {
+                return Some(AsyncDevice::new(addr.to_string(), info));
+            }
+        }
+    }
+}
+
+pub async fn discover(wait: Duration) -> Result<Discovery> {
+    let socket = UdpSocket::bind("0.0.0.0:0").await?;
+    socket.set_broadcast(true)?;
+
+    let cmd = json!({"system": {"get_sysinfo":0}}).to_string();
+    socket
+        .send_to(&encrypt(&cmd, false), "255.255.255.255:9999")
+        .await?;
+
+    Ok(Discovery {
+        socket,
+        deadline: Instant::now() + wait,
+        buf: vec![0; 2048],
+    })
+}
@@ -0,0 +1,741 @@
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::Serialize;
+use serde_json::Value;
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep, timeout};
+
+use crate::codec;
+use crate::command::{Batch, BatchResponse, Command, ANTI_THEFT, COUNTDOWN, SCHEDULE};
+use crate::error::{KasaError, Result};
+use crate::kasa_protocol::{
+    child_at, decrypt, encrypt, with_child_context, RuleId, DEFAULT_MAX_FRAME_LEN,
+};
+use crate::models::{
+    AntiTheftRule, AntiTheftRuleList, CloudInfo, CountdownRule, CountdownRuleList, DayStat,
+    DayStatList, DeviceTime, FirmwareEntry, FirmwareList, Gain, KasaChildren, MonthStat,
+    MonthStatList, Realtime, ScanInfo, ScheduleRule, ScheduleRuleList, SysInfo, Timezone,
+};
+use crate::transport::{is_retryable, is_stale_connection, resolve_addr, ConnectionConfig};
+
+//async counterpart of transport::Transport
+pub trait AsyncTransport {
+    fn send(&mut self, request: &str) -> impl Future<Output = Result<()>> + Send;
+    fn receive(&mut self) -> impl Future<Output = Result<String>> + Send;
+
+    fn query(&mut self, request: &str) -> impl Future<Output = Result<String>> + Send
+    where
+        Self: Send,
+    {
+        async move {
+            self.send(request).await?;
+            self.receive().await
+        }
+    }
+
+    //see Transport::query_idempotent
+    fn query_idempotent(&mut self, request: &str) -> impl Future<Output = Result<String>> + Send
+    where
+        Self: Send,
+    {
+        self.query(request)
+    }
+}
+
+impl AsyncTransport for TcpStream {
+    async fn send(&mut self, request: &str) -> Result<()> {
+        send_kasa_cmd(self, request).await
+    }
+
+    async fn receive(&mut self) -> Result<String> {
+        Ok(decrypt(&read_kasa_resp(self).await?))
+    }
+}
+
+//runs `fut`, giving up after `limit` when one is set
+async fn with_timeout<T>(
+    limit: Option<Duration>,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match limit {
+        Some(limit) => timeout(limit, fut).await.map_err(|_| KasaError::Timeout)?,
+        None => fut.await,
+    }
+}
+
+//async counterpart of transport::TcpTransport, same lazy connect, timeouts
+//and reconnect with backoff
+pub struct AsyncTcpTransport {
+    addr: String,
+    config: ConnectionConfig,
+    stream: Option<TcpStream>,
+}
+
+impl AsyncTcpTransport {
+    pub fn new(addr: &str, config: ConnectionConfig) -> AsyncTcpTransport {
+        AsyncTcpTransport {
+            addr: addr.to_string(),
+            config,
+            stream: None,
+        }
+    }
+
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    pub fn disconnect(&mut self) {
+        self.stream = None;
+    }
+
+    async fn stream(&mut self) -> Result<&mut TcpStream> {
+        if let Some(ref mut stream) = self.stream {
+            return Ok(stream);
+        }
+        let addr = resolve_addr(&self.addr)?;
+        let stream = with_timeout(Some(self.config.connect_timeout), async {
+            Ok(TcpStream::connect(addr).await?)
+        })
+        .await?;
+        Ok(self.stream.insert(stream))
+    }
+
+    fn on_result<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.disconnect();
+        }
+        result
+    }
+
+    //async counterpart of the sync transports' retry loop, see
+    //transport::is_stale_connection for the one resend every command gets
+    async fn query_with_retries(&mut self, request: &str, idempotent: bool) -> Result<String> {
+        let mut attempt = 0;
+        loop {
+            let reused = self.is_connected();
+            let (result, resendable) = match self.send(request).await {
+                Ok(()) => (self.receive().await, idempotent),
+                Err(err) => (Err(err), true),
+            };
+            match result {
+                Err(err) if reused && is_stale_connection(&err) => {}
+                Err(err) if resendable && attempt < self.config.retries && is_retryable(&err) => {
+                    sleep(self.config.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+impl AsyncTransport for AsyncTcpTransport {
+    async fn send(&mut self, request: &str) -> Result<()> {
+        let write_timeout = self.config.write_timeout;
+        let result = match self.stream().await {
+            Ok(stream) => with_timeout(write_timeout, stream.send(request)).await,
+            Err(err) => Err(err),
+        };
+        self.on_result(result)
+    }
+
+    async fn receive(&mut self) -> Result<String> {
+        let read_timeout = self.config.read_timeout;
+        let max_len = self.config.max_frame_len;
+        let result = match self.stream().await {
+            Ok(stream) => {
+                with_timeout(read_timeout, async {
+                    Ok(decrypt(&read_kasa_frame(stream, max_len).await?))
+                })
+                .await
+            }
+            Err(err) => Err(err),
+        };
+        self.on_result(result)
+    }
+
+    async fn query(&mut self, request: &str) -> Result<String> {
+        self.query_with_retries(request, false).await
+    }
+
+    async fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        self.query_with_retries(request, true).await
+    }
+}
+
+async fn read_full<R: AsyncRead + Unpin + ?Sized>(stream: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match stream.read(&mut buf[filled..]).await? {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+//async counterpart of kasa_protocol::read_kasa_frame
+pub async fn read_kasa_frame<R: AsyncRead + Unpin + ?Sized>(
+    stream: &mut R,
+    max_len: usize,
+) -> Result<Vec<u8>> {
+    let mut header = [0u8; codec::HEADER_LEN];
+    let received = read_full(stream, &mut header).await?;
+    if received < header.len() {
+        return Err(KasaError::Truncated {
+            expected: header.len(),
+            received,
+        });
+    }
+    let len = codec::decode_header(header);
+    if len > max_len {
+        return Err(KasaError::FrameTooLarge { len, max: max_len });
+    }
+
+    let mut recv = vec![0u8; len];
+    let received = read_full(stream, &mut recv).await?;
+    if received < len {
+        return Err(KasaError::Truncated {
+            expected: len,
+            received,
+        });
+    }
+    Ok(recv)
+}
+
+pub async fn read_kasa_resp<R: AsyncRead + Unpin + ?Sized>(stream: &mut R) -> Result<Vec<u8>> {
+    read_kasa_frame(stream, DEFAULT_MAX_FRAME_LEN).await
+}
+
+pub async fn send_kasa_cmd<W: AsyncWrite + Unpin + ?Sized>(
+    stream: &mut W,
+    cmd: &str,
+) -> Result<()> {
+    let cmd = encrypt(cmd, true);
+    stream.write_all(&cmd).await?;
+    Ok(())
+}
+
+async fn send_and_read<T: AsyncTransport + Send>(
+    transport: &mut T,
+    cmd: &str,
+    idempotent: bool,
+) -> Result<Value> {
+    let resp = match idempotent {
+        true => transport.query_idempotent(cmd).await?,
+        false => transport.query(cmd).await?,
+    };
+    Ok(serde_json::from_str(&resp)?)
+}
+
+//async counterpart of kasa_protocol::execute
+pub async fn execute<T: AsyncTransport + Send, R: DeserializeOwned>(
+    transport: &mut T,
+    cmd: &Command,
+) -> Result<R> {
+    let resp = send_and_read(transport, &cmd.to_string(), cmd.is_idempotent()).await?;
+    cmd.parse(&resp)
+}
+
+//async counterpart of kasa_protocol::execute_batch
+pub async fn execute_batch<T: AsyncTransport + Send>(
+    transport: &mut T,
+    batch: &Batch,
+) -> Result<BatchResponse> {
+    let resp = send_and_read(
+        transport,
+        &batch.to_json()?.to_string(),
+        batch.is_idempotent(),
+    )
+    .await?;
+    Ok(BatchResponse::new(batch, resp))
+}
+
+//async counterpart of kasa_protocol::query_raw
+pub async fn query_raw<T: AsyncTransport + Send>(
+    transport: &mut T,
+    request: Value,
+    child_id: Option<&str>,
+) -> Result<Value> {
+    send_and_read(
+        transport,
+        &with_child_context(request, child_id)?.to_string(),
+        false,
+    )
+    .await
+}
+
+pub async fn get_sys_info<T: AsyncTransport + Send>(transport: &mut T) -> Result<SysInfo> {
+    execute(transport, &Command::get_sysinfo()).await
+}
+
+pub async fn get_children<T: AsyncTransport + Send>(
+    transport: &mut T,
+) -> Result<Vec<KasaChildren>> {
+    Ok(get_sys_info(transport).await?.children)
+}
+
+pub async fn get_realtime<T: AsyncTransport + Send>(transport: &mut T) -> Result<Realtime> {
+    execute(transport, &Command::get_realtime()).await
+}
+
+pub async fn get_realtime_by_id<T: AsyncTransport + Send>(
+    transport: &mut T,
+    id: &str,
+) -> Result<Realtime> {
+    execute(transport, &Command::get_realtime().child(id)).await
+}
+
+pub async fn get_realtime_by_idx<T: AsyncTransport + Send>(
+    transport: &mut T,
+    idx: usize,
+) -> Result<Realtime> {
+    let children = get_children(transport).await?;
+    let child = child_at(&children, idx)?;
+    get_realtime_by_id(transport, &child.id).await
+}
+
+pub async fn get_all_realtime<T: AsyncTransport + Send>(
+    transport: &mut T,
+) -> Result<Vec<Realtime>> {
+    //same one-request-per-child dance as the blocking version
+    let children = get_children(transport).await?;
+    let mut rts: Vec<Realtime> = vec![];
+
+    for child in children {
+        rts.push(get_realtime_by_id(transport, &child.id).await?);
+    }
+
+    Ok(rts)
+}
+
+pub async fn get_daystat<T: AsyncTransport + Send>(
+    transport: &mut T,
+    year: u16,
+    month: u8,
+) -> Result<Vec<DayStat>> {
+    let stats: DayStatList = execute(transport, &Command::get_daystat(year, month)).await?;
+    Ok(stats.day_list)
+}
+
+pub async fn get_daystat_by_id<T: AsyncTransport + Send>(
+    transport: &mut T,
+    id: &str,
+    year: u16,
+    month: u8,
+) -> Result<Vec<DayStat>> {
+    let stats: DayStatList =
+        execute(transport, &Command::get_daystat(year, month).child(id)).await?;
+    Ok(stats.day_list)
+}
+
+pub async fn get_monthstat<T: AsyncTransport + Send>(
+    transport: &mut T,
+    year: u16,
+) -> Result<Vec<MonthStat>> {
+    let stats: MonthStatList = execute(transport, &Command::get_monthstat(year)).await?;
+    Ok(stats.month_list)
+}
+
+pub async fn get_monthstat_by_id<T: AsyncTransport + Send>(
+    transport: &mut T,
+    id: &str,
+    year: u16,
+) -> Result<Vec<MonthStat>> {
+    let stats: MonthStatList = execute(transport, &Command::get_monthstat(year).child(id)).await?;
+    Ok(stats.month_list)
+}
+
+pub async fn erase_emeter_stat<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::erase_emeter_stat().maybe_child(child_id),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_vgain_igain<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<Gain> {
+    execute(transport, &Command::get_vgain_igain().maybe_child(child_id)).await
+}
+
+pub async fn set_vgain<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    vgain: u32,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_vgain(vgain).maybe_child(child_id)).await?;
+    Ok(())
+}
+
+pub async fn set_igain<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    igain: u32,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_igain(igain).maybe_child(child_id)).await?;
+    Ok(())
+}
+
+//async counterparts of the rule helpers in kasa_protocol
+async fn get_rules<T: AsyncTransport + Send, R: DeserializeOwned>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+) -> Result<R> {
+    execute(transport, &Command::get_rules(module).maybe_child(child_id)).await
+}
+
+async fn add_rule<T: AsyncTransport + Send, R: Serialize>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    rule: &R,
+) -> Result<String> {
+    let cmd = Command::add_rule(module, serde_json::to_value(rule)?).maybe_child(child_id);
+    let added: RuleId = execute(transport, &cmd).await?;
+    Ok(added.id)
+}
+
+async fn edit_rule<T: AsyncTransport + Send, R: Serialize>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    rule: &R,
+) -> Result<()> {
+    let cmd = Command::edit_rule(module, serde_json::to_value(rule)?).maybe_child(child_id);
+    execute::<_, IgnoredAny>(transport, &cmd).await?;
+    Ok(())
+}
+
+async fn delete_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::delete_rule(module, id).maybe_child(child_id),
+    )
+    .await?;
+    Ok(())
+}
+
+async fn delete_all_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::delete_all_rules(module).maybe_child(child_id),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_schedule_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<ScheduleRuleList> {
+    get_rules(transport, SCHEDULE, child_id).await
+}
+
+//async counterpart of kasa_protocol::add_schedule_rule
+pub async fn add_schedule_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &ScheduleRule,
+) -> Result<String> {
+    add_rule(transport, SCHEDULE, child_id, rule).await
+}
+
+//async counterpart of kasa_protocol::edit_schedule_rule
+pub async fn edit_schedule_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &ScheduleRule,
+) -> Result<()> {
+    edit_rule(transport, SCHEDULE, child_id, rule).await
+}
+
+pub async fn delete_schedule_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    delete_rule(transport, SCHEDULE, child_id, id).await
+}
+
+pub async fn delete_all_schedule_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, SCHEDULE, child_id).await
+}
+
+//async counterpart of kasa_protocol::set_schedule_enabled
+pub async fn set_schedule_enabled<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    enable: bool,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::set_overall_enable(SCHEDULE, enable).maybe_child(child_id),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_countdown_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<CountdownRuleList> {
+    get_rules(transport, COUNTDOWN, child_id).await
+}
+
+//async counterpart of kasa_protocol::add_countdown_rule
+pub async fn add_countdown_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &CountdownRule,
+) -> Result<String> {
+    add_rule(transport, COUNTDOWN, child_id, rule).await
+}
+
+pub async fn edit_countdown_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &CountdownRule,
+) -> Result<()> {
+    edit_rule(transport, COUNTDOWN, child_id, rule).await
+}
+
+pub async fn delete_all_countdown_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, COUNTDOWN, child_id).await
+}
+
+pub async fn get_anti_theft_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<AntiTheftRuleList> {
+    get_rules(transport, ANTI_THEFT, child_id).await
+}
+
+pub async fn add_anti_theft_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &AntiTheftRule,
+) -> Result<String> {
+    add_rule(transport, ANTI_THEFT, child_id, rule).await
+}
+
+pub async fn edit_anti_theft_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &AntiTheftRule,
+) -> Result<()> {
+    edit_rule(transport, ANTI_THEFT, child_id, rule).await
+}
+
+pub async fn delete_anti_theft_rule<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    delete_rule(transport, ANTI_THEFT, child_id, id).await
+}
+
+pub async fn delete_all_anti_theft_rules<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, ANTI_THEFT, child_id).await
+}
+
+pub async fn set_anti_theft_enabled<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    enable: bool,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::set_overall_enable(ANTI_THEFT, enable).maybe_child(child_id),
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn get_time<T: AsyncTransport + Send>(transport: &mut T) -> Result<DeviceTime> {
+    execute(transport, &Command::get_time()).await
+}
+
+pub async fn get_timezone<T: AsyncTransport + Send>(transport: &mut T) -> Result<Timezone> {
+    execute(transport, &Command::get_timezone()).await
+}
+
+//async counterpart of kasa_protocol::set_timezone
+pub async fn set_timezone<T: AsyncTransport + Send>(
+    transport: &mut T,
+    index: u8,
+    time: &DeviceTime,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_timezone(index, time)).await?;
+    Ok(())
+}
+
+pub async fn get_cloud_info<T: AsyncTransport + Send>(transport: &mut T) -> Result<CloudInfo> {
+    execute(transport, &Command::get_cloud_info()).await
+}
+
+pub async fn bind_cloud<T: AsyncTransport + Send>(
+    transport: &mut T,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::bind_cloud(username, password)).await?;
+    Ok(())
+}
+
+//async counterpart of kasa_protocol::unbind_cloud
+pub async fn unbind_cloud<T: AsyncTransport + Send>(transport: &mut T) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::unbind_cloud()).await?;
+    Ok(())
+}
+
+//async counterpart of kasa_protocol::set_cloud_server_url
+pub async fn set_cloud_server_url<T: AsyncTransport + Send>(
+    transport: &mut T,
+    server: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_cloud_server_url(server)).await?;
+    Ok(())
+}
+
+//async counterpart of kasa_protocol::get_intl_fw_list
+pub async fn get_intl_fw_list<T: AsyncTransport + Send>(
+    transport: &mut T,
+) -> Result<Vec<FirmwareEntry>> {
+    let list: FirmwareList = execute(transport, &Command::get_intl_fw_list()).await?;
+    Ok(list.fw_list)
+}
+
+pub async fn get_scaninfo<T: AsyncTransport + Send>(
+    transport: &mut T,
+    refresh: bool,
+) -> Result<ScanInfo> {
+    execute(transport, &Command::get_scaninfo(refresh)).await
+}
+
+pub async fn set_stainfo<T: AsyncTransport + Send>(
+    transport: &mut T,
+    ssid: &str,
+    password: &str,
+    key_type: u8,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_stainfo(ssid, password, key_type)).await?;
+    Ok(())
+}
+
+pub async fn reboot<T: AsyncTransport + Send>(transport: &mut T, delay: u32) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::reboot(delay)).await?;
+    Ok(())
+}
+
+pub async fn factory_reset<T: AsyncTransport + Send>(transport: &mut T, delay: u32) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::reset(delay)).await?;
+    Ok(())
+}
+
+pub async fn set_led_off<T: AsyncTransport + Send>(transport: &mut T, off: bool) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_led_off(off)).await?;
+    Ok(())
+}
+
+pub async fn set_dev_location<T: AsyncTransport + Send>(
+    transport: &mut T,
+    latitude: f64,
+    longitude: f64,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_location(latitude, longitude)).await?;
+    Ok(())
+}
+
+pub async fn set_dev_alias<T: AsyncTransport + Send>(transport: &mut T, alias: &str) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_alias(alias)).await?;
+    Ok(())
+}
+
+pub async fn toggle_relay_by_idx<T: AsyncTransport + Send>(
+    transport: &mut T,
+    idx: usize,
+) -> Result<()> {
+    let children = get_children(transport).await?;
+    let child = child_at(&children, idx)?;
+    let state = match child.state {
+        0 => 1,
+        _ => 0,
+    };
+    set_relay_by_child_id(transport, &child.id, state).await
+}
+
+pub async fn set_relay_by_idx<T: AsyncTransport + Send>(
+    transport: &mut T,
+    idx: usize,
+    state: u8,
+) -> Result<()> {
+    let children = get_children(transport).await?;
+    let child = child_at(&children, idx)?;
+    set_relay_by_child_id(transport, &child.id, state).await
+}
+
+pub async fn toggle_single_relay_outlet<T: AsyncTransport + Send>(transport: &mut T) -> Result<()> {
+    let state = match get_sys_info(transport).await?.relay_state {
+        0 => 1,
+        _ => 0,
+    };
+    set_single_relay_outlet(transport, state).await
+}
+
+pub async fn set_single_relay_outlet<T: AsyncTransport + Send>(
+    transport: &mut T,
+    state: u8,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_relay_state(state)).await?;
+    Ok(())
+}
+
+pub async fn set_relay_by_child_id<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: &str,
+    state: u8,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_relay_state(state).child(child_id)).await?;
+    Ok(())
+}
+
+pub async fn set_outlet_alias<T: AsyncTransport + Send>(
+    transport: &mut T,
+    child_id: &str,
+    alias: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_alias(alias).child(child_id)).await?;
+    Ok(())
+}
@@ -0,0 +1,115 @@
+//setting device clocks from the host. power cuts leave the plugs with a
+//drifted clock (or none at all), which throws schedules off until the app
+//next talks to them. the host zone is matched against the kasa timezone
+//table by name, falling back to any entry that keeps the same offsets
+use jiff::tz::TimeZone;
+use jiff::{SignedDuration, Timestamp};
+use std::time::Duration;
+
+use crate::credentials::Credentials;
+use crate::device::{discover_all, Device, DiscoveredDevice};
+use crate::error::{KasaError, Result};
+use crate::kasa_protocol::set_timezone;
+use crate::models::DeviceTime;
+use crate::timezones::{self, TIMEZONES};
+use crate::transport::Transport;
+
+fn zone(name: &str) -> Result<TimeZone> {
+    TimeZone::get(name).map_err(|err| KasaError::Unsupported(format!("timezone {name}: {err}")))
+}
+
+//an entry matches if it agrees with the host at quarterly points over the
+//next year, so a zone with dst isn't matched to one without
+fn same_offsets(a: &TimeZone, b: &TimeZone, now: Timestamp) -> bool {
+    (0..4).all(|quarter| {
+        let at = now
+            .checked_add(SignedDuration::from_hours(24 * 91 * quarter))
+            .unwrap_or(now);
+        a.to_offset(at) == b.to_offset(at)
+    })
+}
+
+//the kasa timezone index for the host's local zone
+pub fn host_timezone_index() -> Result<u8> {
+    let host = TimeZone::system();
+    if let Some(index) = host.iana_name().and_then(timezones::index_of) {
+        return Ok(index);
+    }
+    let now = Timestamp::now();
+    TIMEZONES
+        .iter()
+        .position(|name| zone(name).is_ok_and(|tz| same_offsets(&host, &tz, now)))
+        .map(|index| index as u8)
+        .ok_or_else(|| {
+            KasaError::Unsupported(format!(
+                "no kasa timezone matches the host zone {}",
+                host.iana_name().unwrap_or("(unnamed)")
+            ))
+        })
+}
+
+//the host clock as local time in the given kasa timezone
+pub fn host_time_in(index: u8) -> Result<DeviceTime> {
+    let name = timezones::iana_name(index)
+        .ok_or_else(|| KasaError::Unsupported(format!("timezone index {index}")))?;
+    let now = Timestamp::now().to_zoned(zone(name)?);
+    Ok(DeviceTime {
+        year: now.year() as u16,
+        month: now.month() as u8,
+        mday: now.day() as u8,
+        hour: now.hour() as u8,
+        min: now.minute() as u8,
+        sec: now.second() as u8,
+    })
+}
+
+//sets the device's zone and clock from the host. index picks the zone,
+//None uses the host's own. returns what was sent
+pub fn sync_clock<T: Transport + ?Sized>(
+    transport: &mut T,
+    index: Option<u8>,
+) -> Result<(u8, DeviceTime)> {
+    let index = match index {
+        Some(index) => index,
+        None => host_timezone_index()?,
+    };
+    let time = host_time_in(index)?;
+    set_timezone(transport, index, &time)?;
+    Ok((index, time))
+}
+
+//sync_clock for every device that answers discovery, results are per ip.
+//klap devices use the given account, or without one whatever the handshake
+//falls back to (the kasa setup login or blank), which is enough for devices
+//that were never bound. tapo devices don't have the kasa time module and
+//come back as unsupported
+pub fn sync_fleet(
+    wait: Duration,
+    index: Option<u8>,
+    credentials: Option<&Credentials>,
+) -> Result<Vec<(String, Result<DeviceTime>)>> {
+    let index = match index {
+        Some(index) => index,
+        None => host_timezone_index()?,
+    };
+    let results = discover_all(wait)?
+        .into_iter()
+        .map(|found| {
+            let result = sync_discovered(&found, index, credentials);
+            (found.ip_addr, result)
+        })
+        .collect();
+    Ok(results)
+}
+
+fn sync_discovered(
+    found: &DiscoveredDevice,
+    index: u8,
+    credentials: Option<&Credentials>,
+) -> Result<DeviceTime> {
+    let credentials = credentials.cloned().unwrap_or_else(Credentials::blank);
+    let device = Device::from_discovered(found, credentials)?;
+    let time = host_time_in(index)?;
+    device.set_timezone(index, &time)?;
+    Ok(time)
+}
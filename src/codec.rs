@@ -0,0 +1,100 @@
+//allocation free pieces of the legacy protocol, usable from no_std targets.
+//everything works on caller supplied buffers so it can sit on top of whatever
+//network stack the target has
+use core::fmt;
+
+//just the initial key
+pub const INITIAL_KEY: u8 = 171;
+//frames on the tcp transport are prefixed with a big endian u32 payload length
+pub const HEADER_LEN: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodecError {
+    //the output buffer can't hold the encoded frame
+    BufferTooSmall { needed: usize, available: usize },
+    //the frame advertises more payload than was supplied
+    Truncated { expected: usize, available: usize },
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CodecError::BufferTooSmall { needed, available } => {
+                write!(f, "buffer too small: need {needed} bytes, have {available}")
+            }
+            CodecError::Truncated {
+                expected,
+                available,
+            } => write!(
+                f,
+                "truncated frame: expected {expected} bytes, got {available}"
+            ),
+        }
+    }
+}
+
+// https://github.com/softScheck/tplink-smartplug/blob/master/tplink_smartplug.py#L70
+//each output byte becomes the key for the next one
+pub fn encode_in_place(buf: &mut [u8]) {
+    let mut key = INITIAL_KEY;
+    for b in buf.iter_mut() {
+        *b ^= key;
+        key = *b;
+    }
+}
+
+//inverse of encode_in_place, here the key is the previous ciphertext byte
+pub fn decode_in_place(buf: &mut [u8]) {
+    let mut key = INITIAL_KEY;
+    for b in buf.iter_mut() {
+        let c = *b;
+        *b ^= key;
+        key = c;
+    }
+}
+
+pub fn encode_header(payload_len: usize) -> [u8; HEADER_LEN] {
+    (payload_len as u32).to_be_bytes()
+}
+
+pub fn decode_header(header: [u8; HEADER_LEN]) -> usize {
+    u32::from_be_bytes(header) as usize
+}
+
+//writes the length prefix and encoded payload into `out`, returning the frame length
+pub fn encode_frame(payload: &[u8], out: &mut [u8]) -> Result<usize, CodecError> {
+    let needed = HEADER_LEN + payload.len();
+    if out.len() < needed {
+        return Err(CodecError::BufferTooSmall {
+            needed,
+            available: out.len(),
+        });
+    }
+    out[..HEADER_LEN].copy_from_slice(&encode_header(payload.len()));
+    out[HEADER_LEN..needed].copy_from_slice(payload);
+    encode_in_place(&mut out[HEADER_LEN..needed]);
+    Ok(needed)
+}
+
+//decodes a complete frame in place and hands back the plaintext payload
+pub fn decode_frame(frame: &mut [u8]) -> Result<&mut [u8], CodecError> {
+    if frame.len() < HEADER_LEN {
+        return Err(CodecError::Truncated {
+            expected: HEADER_LEN,
+            available: frame.len(),
+        });
+    }
+    let mut header = [0u8; HEADER_LEN];
+    header.copy_from_slice(&frame[..HEADER_LEN]);
+    let len = decode_header(header);
+    let available = frame.len() - HEADER_LEN;
+    if available < len {
+        return Err(CodecError::Truncated {
+            expected: len,
+            available,
+        });
+    }
+    let payload = &mut frame[HEADER_LEN..HEADER_LEN + len];
+    decode_in_place(payload);
+    Ok(payload)
+}
@@ -0,0 +1,410 @@
+//a single kasa call, {"module": {"method": params}}, optionally aimed at
+//one outlet of a strip through {"context": {"child_ids": [..]}}.
+//building requests and unpacking replies both go through here, so the
+//err_code handling lives in one place
+use serde::de::DeserializeOwned;
+use serde_json::{json, Map, Value};
+use std::fmt;
+
+use crate::error::{KasaError, Result};
+use crate::models::DeviceTime;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Command {
+    module: String,
+    method: String,
+    params: Value,
+    child_ids: Vec<String>,
+    idempotent: bool,
+}
+
+impl Command {
+    //a call with null params, which is what every getter takes
+    pub fn new(module: &str, method: &str) -> Command {
+        Command {
+            module: module.to_string(),
+            method: method.to_string(),
+            params: Value::Null,
+            child_ids: vec![],
+            idempotent: false,
+        }
+    }
+
+    pub fn params(mut self, params: Value) -> Command {
+        self.params = params;
+        self
+    }
+
+    //target one outlet. the field is a list, but the devices only
+    //ever act on the first entry, so it's one child per command
+    pub fn child(mut self, child_id: &str) -> Command {
+        self.child_ids = vec![child_id.to_string()];
+        self
+    }
+
+    //marks the call as safe to send twice, so a transport may resend it when
+    //the reply is lost. getters and setters that set a fixed value are,
+    //anything that adds, deletes, erases, (un)binds or restarts must not be
+    pub fn idempotent(mut self) -> Command {
+        self.idempotent = true;
+        self
+    }
+
+    pub fn maybe_child(self, child_id: Option<&str>) -> Command {
+        match child_id {
+            Some(child_id) => self.child(child_id),
+            None => self,
+        }
+    }
+
+    pub fn module(&self) -> &str {
+        &self.module
+    }
+
+    pub fn method(&self) -> &str {
+        &self.method
+    }
+
+    pub fn child_id(&self) -> Option<&str> {
+        self.child_ids.first().map(String::as_str)
+    }
+
+    pub fn is_idempotent(&self) -> bool {
+        self.idempotent
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut method = Map::new();
+        method.insert(self.method.clone(), self.params.clone());
+        let mut request = Map::new();
+        request.insert(self.module.clone(), Value::Object(method));
+        if !self.child_ids.is_empty() {
+            request.insert(
+                "context".to_string(),
+                json!({ "child_ids": self.child_ids }),
+            );
+        }
+        Value::Object(request)
+    }
+
+    //pull resp[module][method] out of a reply, surfacing any err_code along the way
+    pub fn extract<'a>(&self, resp: &'a Value) -> Result<&'a Value> {
+        let module_resp = resp
+            .get(&self.module)
+            .ok_or_else(|| KasaError::MissingField(self.module.clone()))?;
+        check_err_code(module_resp)?;
+        let method_resp = module_resp
+            .get(&self.method)
+            .ok_or_else(|| KasaError::MissingField(format!("{}.{}", self.module, self.method)))?;
+        check_err_code(method_resp)?;
+        Ok(method_resp)
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, resp: &Value) -> Result<T> {
+        Ok(T::deserialize(self.extract(resp)?)?)
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_json())
+    }
+}
+
+//several commands sent as one request, e.g. sysinfo, realtime and the time in
+//a single round trip. the devices only honour one child context per request,
+//so every command in a batch has to target the same outlet (or none)
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Batch {
+    commands: Vec<Command>,
+}
+
+impl Batch {
+    pub fn new() -> Batch {
+        Batch::default()
+    }
+
+    pub fn with(mut self, cmd: Command) -> Batch {
+        self.commands.push(cmd);
+        self
+    }
+
+    pub fn commands(&self) -> &[Command] {
+        &self.commands
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    //only as safe to resend as its least safe command
+    pub fn is_idempotent(&self) -> bool {
+        self.commands.iter().all(Command::is_idempotent)
+    }
+
+    pub fn to_json(&self) -> Result<Value> {
+        let child_id = self.commands.first().and_then(Command::child_id);
+        let mut request = Map::new();
+        for cmd in &self.commands {
+            if cmd.child_id() != child_id {
+                return Err(KasaError::InvalidBatch(format!(
+                    "{}.{} targets a different child than the rest of the batch",
+                    cmd.module, cmd.method
+                )));
+            }
+            let module = request
+                .entry(cmd.module.clone())
+                .or_insert_with(|| Value::Object(Map::new()));
+            if let Some(methods) = module.as_object_mut() {
+                if methods.contains_key(&cmd.method) {
+                    return Err(KasaError::InvalidBatch(format!(
+                        "{}.{} appears twice",
+                        cmd.module, cmd.method
+                    )));
+                }
+                methods.insert(cmd.method.clone(), cmd.params.clone());
+            }
+        }
+        if let Some(child_id) = child_id {
+            request.insert("context".to_string(), json!({ "child_ids": [child_id] }));
+        }
+        Ok(Value::Object(request))
+    }
+}
+
+//the reply to a Batch. each part is checked on its own, so a module the
+//device doesn't support only fails that part
+#[derive(Clone, Debug)]
+pub struct BatchResponse {
+    commands: Vec<Command>,
+    resp: Value,
+}
+
+impl BatchResponse {
+    pub fn new(batch: &Batch, resp: Value) -> BatchResponse {
+        BatchResponse {
+            commands: batch.commands.clone(),
+            resp,
+        }
+    }
+
+    pub fn raw(&self) -> &Value {
+        &self.resp
+    }
+
+    //the part for the idx'th command in the batch
+    pub fn part<T: DeserializeOwned>(&self, idx: usize) -> Result<T> {
+        let cmd = self.commands.get(idx).ok_or_else(|| {
+            KasaError::InvalidBatch(format!(
+                "no command {idx} in a batch of {}",
+                self.commands.len()
+            ))
+        })?;
+        cmd.parse(&self.resp)
+    }
+
+    //the part for a given command, matched on module and method
+    pub fn get<T: DeserializeOwned>(&self, cmd: &Command) -> Result<T> {
+        cmd.parse(&self.resp)
+    }
+}
+
+//the device reports failures as {"err_code": -1, "err_msg": "module not support"}
+//either at the module level or inside the method it was asked to run
+pub fn check_err_code(value: &Value) -> Result<()> {
+    match value.get("err_code").and_then(Value::as_i64) {
+        None | Some(0) => Ok(()),
+        Some(err_code) => Err(KasaError::Device {
+            err_code,
+            err_msg: value
+                .get("err_msg")
+                .and_then(Value::as_str)
+                .map(String::from),
+        }),
+    }
+}
+
+//modules sharing the get_rules/add_rule/edit_rule/.. method set
+pub const SCHEDULE: &str = "schedule";
+pub const COUNTDOWN: &str = "count_down";
+pub const ANTI_THEFT: &str = "anti_theft";
+
+//the calls the rest of the crate makes
+impl Command {
+    pub fn get_sysinfo() -> Command {
+        Command::new("system", "get_sysinfo").idempotent()
+    }
+
+    pub fn get_realtime() -> Command {
+        Command::new("emeter", "get_realtime").idempotent()
+    }
+
+    //the days of one month that have any usage recorded
+    pub fn get_daystat(year: u16, month: u8) -> Command {
+        Command::new("emeter", "get_daystat")
+            .params(json!({ "year": year, "month": month }))
+            .idempotent()
+    }
+
+    pub fn get_monthstat(year: u16) -> Command {
+        Command::new("emeter", "get_monthstat")
+            .params(json!({ "year": year }))
+            .idempotent()
+    }
+
+    //clears the daily and monthly totals, realtime readings are unaffected
+    pub fn erase_emeter_stat() -> Command {
+        Command::new("emeter", "erase_emeter_stat")
+    }
+
+    pub fn get_vgain_igain() -> Command {
+        Command::new("emeter", "get_vgain_igain").idempotent()
+    }
+
+    pub fn set_vgain(vgain: u32) -> Command {
+        Command::new("emeter", "set_vgain")
+            .params(json!({ "vgain": vgain }))
+            .idempotent()
+    }
+
+    pub fn set_igain(igain: u32) -> Command {
+        Command::new("emeter", "set_igain")
+            .params(json!({ "igain": igain }))
+            .idempotent()
+    }
+
+    pub fn get_rules(module: &str) -> Command {
+        Command::new(module, "get_rules").idempotent()
+    }
+
+    //rule is the module's rule type serialised, without an id
+    pub fn add_rule(module: &str, rule: Value) -> Command {
+        Command::new(module, "add_rule").params(rule)
+    }
+
+    //rule is the module's rule type serialised, including the id to replace
+    pub fn edit_rule(module: &str, rule: Value) -> Command {
+        Command::new(module, "edit_rule").params(rule)
+    }
+
+    pub fn delete_rule(module: &str, id: &str) -> Command {
+        Command::new(module, "delete_rule").params(json!({ "id": id }))
+    }
+
+    pub fn delete_all_rules(module: &str) -> Command {
+        Command::new(module, "delete_all_rules")
+    }
+
+    pub fn set_overall_enable(module: &str, enable: bool) -> Command {
+        Command::new(module, "set_overall_enable")
+            .params(json!({ "enable": u8::from(enable) }))
+            .idempotent()
+    }
+
+    pub fn get_time() -> Command {
+        Command::new("time", "get_time").idempotent()
+    }
+
+    pub fn get_timezone() -> Command {
+        Command::new("time", "get_timezone").idempotent()
+    }
+
+    //the firmware sets the zone and the clock together, so the time goes in
+    //the same call, local to the new zone
+    pub fn set_timezone(index: u8, time: &DeviceTime) -> Command {
+        Command::new("time", "set_timezone")
+            .params(json!({
+                "index": index,
+                "year": time.year,
+                "month": time.month,
+                "mday": time.mday,
+                "hour": time.hour,
+                "min": time.min,
+                "sec": time.sec,
+            }))
+            .idempotent()
+    }
+
+    pub fn get_cloud_info() -> Command {
+        Command::new("cnCloud", "get_info").idempotent()
+    }
+
+    //ties the device to a tp-link account
+    pub fn bind_cloud(username: &str, password: &str) -> Command {
+        Command::new("cnCloud", "bind")
+            .params(json!({ "username": username, "password": password }))
+    }
+
+    pub fn unbind_cloud() -> Command {
+        Command::new("cnCloud", "unbind")
+    }
+
+    pub fn set_cloud_server_url(server: &str) -> Command {
+        Command::new("cnCloud", "set_server_url")
+            .params(json!({ "server": server }))
+            .idempotent()
+    }
+
+    pub fn get_intl_fw_list() -> Command {
+        Command::new("cnCloud", "get_intl_fw_list").idempotent()
+    }
+
+    //refresh makes the device scan again rather than return its last results
+    pub fn get_scaninfo(refresh: bool) -> Command {
+        Command::new("netif", "get_scaninfo")
+            .params(json!({ "refresh": u8::from(refresh) }))
+            .idempotent()
+    }
+
+    pub fn set_stainfo(ssid: &str, password: &str, key_type: u8) -> Command {
+        Command::new("netif", "set_stainfo").params(json!({
+            "ssid": ssid,
+            "password": password,
+            "key_type": key_type,
+        }))
+    }
+
+    pub fn set_relay_state(state: u8) -> Command {
+        Command::new("system", "set_relay_state")
+            .params(json!({ "state": state }))
+            .idempotent()
+    }
+
+    pub fn reboot(delay: u32) -> Command {
+        Command::new("system", "reboot").params(json!({ "delay": delay }))
+    }
+
+    //factory reset, the device forgets its wifi and goes back to setup mode
+    pub fn reset(delay: u32) -> Command {
+        Command::new("system", "reset").params(json!({ "delay": delay }))
+    }
+
+    pub fn set_led_off(off: bool) -> Command {
+        Command::new("system", "set_led_off")
+            .params(json!({ "off": u8::from(off) }))
+            .idempotent()
+    }
+
+    //older firmware reads latitude/longitude in degrees, newer the _i fields
+    //in ten-thousandths of a degree, like sysinfo reports them. both are sent
+    pub fn set_dev_location(latitude: f64, longitude: f64) -> Command {
+        Command::new("system", "set_dev_location")
+            .params(json!({
+                "latitude": latitude,
+                "longitude": longitude,
+                "latitude_i": (latitude * 10000.0).round() as i32,
+                "longitude_i": (longitude * 10000.0).round() as i32,
+            }))
+            .idempotent()
+    }
+
+    pub fn set_dev_alias(alias: &str) -> Command {
+        Command::new("system", "set_dev_alias")
+            .params(json!({ "alias": alias }))
+            .idempotent()
+    }
+}
@@ -0,0 +1,25 @@
+//login for the transports that authenticate (klap and the tapo passthrough),
+//the same tp-link cloud account the phone app uses
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    pub fn new(username: &str, password: &str) -> Credentials {
+        Credentials {
+            username: username.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    //what factory fresh and never-bound devices accept
+    pub fn kasa_default() -> Credentials {
+        Credentials::new("kasa@tp-link.net", "kasaSetup")
+    }
+
+    pub fn blank() -> Credentials {
+        Credentials::default()
+    }
+}
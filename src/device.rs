@@ -1,66 +1,285 @@
+#[cfg(feature = "smart")]
+use crate::aes_transport::AesTransport;
+use crate::command::{Batch, BatchResponse, Command};
+use crate::credentials::Credentials;
+use crate::error::{KasaError, Result};
 use crate::kasa_protocol::{
-    self, decrypt, deserialize, encrypt, get_sys_info, set_relay_by_child_id, set_single_relay_outlet, toggle_relay_by_idx, toggle_single_relay_outlet, set_relay_by_idx
+    self, decrypt, deserialize, encrypt, get_sys_info, set_relay_by_idx, set_single_relay_outlet,
+    toggle_relay_by_idx, toggle_single_relay_outlet,
 };
-use crate::models::{KasaChildren, KasaResp, Realtime, SysInfo, System};
+#[cfg(feature = "klap")]
+use crate::klap::KlapTransport;
+use crate::models::{
+    CloudInfo, CnCloud, DeviceTime, DiscoveryResp, DiscoveryResult, KasaChildren, KasaResp,
+    Realtime, SysInfo, System,
+};
+#[cfg(feature = "smart")]
+use crate::smart_models::{EnergyUsage, SmartDeviceInfo};
+#[cfg(feature = "smart")]
+use crate::smart_protocol;
+use crate::transport::{ConnectionConfig, TcpTransport, Transport, UdpTransport};
 use crate::validate_ip;
-use anyhow::{anyhow, Result};
 use serde_json::json;
+use std::fmt;
 use std::io;
-use std::net::TcpStream;
 use std::net::UdpSocket;
+use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
+//which command set a device speaks, independent of how the bytes get there
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Protocol {
+    //kasa's {"module": {"method": params}} json, see kasa_protocol
+    Kasa,
+    //tapo's {"method": .., "params": ..} json-rpc, see smart_protocol
+    #[cfg(feature = "smart")]
+    Smart,
+}
+
+type SharedTransport = Arc<Mutex<Box<dyn Transport + Send>>>;
+
+//which of the xor transports a Device built for itself, so with_config can
+//rebuild it. Custom is anything handed in through with_transport
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Link {
+    Tcp,
+    Udp,
+    Custom,
+}
+
+//clones share the same connection, so a Device can be handed to several
+//threads and their commands will queue up on the one socket
 #[derive(Clone)]
 pub struct Device {
     pub ip_addr: String,
     pub kasa_info: KasaResp,
     pub realtime: Vec<Realtime>,
+    pub protocol: Protocol,
+    config: ConnectionConfig,
+    connection: SharedTransport,
+    link: Link,
 }
 
 impl Device {
     pub fn new(ip_addr: String, kasa_info: KasaResp) -> Device {
+        let config = ConnectionConfig::default();
         Device {
+            connection: Arc::new(Mutex::new(Box::new(TcpTransport::new(&ip_addr, config)))),
             ip_addr,
             kasa_info,
             realtime: vec![],
+            protocol: Protocol::Kasa,
+            config,
+            link: Link::Tcp,
+        }
+    }
+
+    //for devices reached some other way than xor over tcp, e.g. a KlapTransport
+    pub fn with_transport(
+        ip_addr: String,
+        protocol: Protocol,
+        transport: Box<dyn Transport + Send>,
+    ) -> Device {
+        Device {
+            ip_addr,
+            kasa_info: KasaResp {
+                system: None,
+                emeter: None,
+                cloud: None,
+            },
+            realtime: vec![],
+            protocol,
+            config: ConnectionConfig::default(),
+            connection: Arc::new(Mutex::new(transport)),
+            link: Link::Custom,
         }
     }
 
-    pub fn get_children(&self) -> Option<Vec<KasaChildren>> {
-        let stream = TcpStream::connect(self.ip_addr.clone());
-        if let Ok(mut strm) = stream {
-            let children = match kasa_protocol::get_children(&mut strm) {
-                Ok(chldrn) => chldrn,
-                Err(_err) => vec![],
-            };
-            return Some(children);
+    //builds a Device with the transport and protocol discovery said it needs
+    pub fn from_discovered(found: &DiscoveredDevice, credentials: Credentials) -> Result<Device> {
+        let config = ConnectionConfig::default();
+        //the device_type from 20002 discovery is e.g. "SMART.TAPOPLUG" or "IOT.SMARTPLUGSWITCH"
+        let is_smart = found
+            .discovery
+            .as_ref()
+            .is_some_and(|d| d.device_type.starts_with("SMART."));
+        let protocol = match is_smart {
+            #[cfg(feature = "smart")]
+            true => Protocol::Smart,
+            //kasa commands would only get errors back from a tapo device
+            #[cfg(not(feature = "smart"))]
+            true => return Err(KasaError::Unsupported("smart feature disabled".to_string())),
+            false => Protocol::Kasa,
+        };
+
+        let transport: Box<dyn Transport + Send> = match &found.transport {
+            DeviceTransport::Xor => Box::new(TcpTransport::new(&found.ip_addr, config)),
+            #[cfg(feature = "klap")]
+            DeviceTransport::Klap { port, .. } => Box::new(KlapTransport::new(
+                &format!("{}:{port}", found.ip_addr),
+                credentials,
+                config,
+            )),
+            #[cfg(feature = "smart")]
+            DeviceTransport::Aes { port, .. } => Box::new(AesTransport::new(
+                &format!("{}:{port}", found.ip_addr),
+                credentials,
+                config,
+            )),
+            other => {
+                let _ = credentials;
+                return Err(KasaError::Unsupported(format!(
+                    "no transport available for {other:?}"
+                )));
+            }
+        };
+
+        let mut device = Device::with_transport(found.ip_addr.clone(), protocol, transport);
+        if found.transport == DeviceTransport::Xor {
+            device.link = Link::Tcp;
+        }
+        if let Some(sysinfo) = found.sysinfo.clone() {
+            device.kasa_info.system = Some(System {
+                get_sysinfo: Some(sysinfo),
+            });
+        }
+        Ok(device)
+    }
+
+    fn xor_transport(&self, link: Link) -> Option<Box<dyn Transport + Send>> {
+        match link {
+            Link::Tcp => Some(Box::new(TcpTransport::new(&self.ip_addr, self.config))),
+            Link::Udp => Some(Box::new(UdpTransport::new(&self.ip_addr, self.config))),
+            Link::Custom => None,
+        }
+    }
+
+    //swaps in a fresh xor connection with the given timeouts, dropping any open socket.
+    //devices built with with_transport carry their own config and are left alone
+    pub fn with_config(mut self, config: ConnectionConfig) -> Device {
+        self.config = config;
+        if let Some(transport) = self.xor_transport(self.link) {
+            self.connection = Arc::new(Mutex::new(transport));
+        }
+        self
+    }
+
+    //only a kasa device on an xor link we built ourselves can be moved between
+    //tcp and udp, anything handed in through with_transport would lose its session
+    fn switch_link(mut self, link: Link) -> Result<Device> {
+        if self.link == Link::Custom || self.protocol != Protocol::Kasa {
+            return Err(KasaError::Unsupported(
+                "only kasa devices on a tcp or udp link can switch between them".to_string(),
+            ));
+        }
+        if let Some(transport) = self.xor_transport(link) {
+            self.link = link;
+            self.connection = Arc::new(Mutex::new(transport));
+        }
+        Ok(self)
+    }
+
+    //send commands as udp datagrams to 9999 instead of over a tcp connection
+    pub fn over_udp(self) -> Result<Device> {
+        self.switch_link(Link::Udp)
+    }
+
+    pub fn over_tcp(self) -> Result<Device> {
+        self.switch_link(Link::Tcp)
+    }
+
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    //the socket is opened on first use and kept for every call after,
+    //TcpTransport takes care of reconnecting when the device drops it
+    fn transport(&self) -> MutexGuard<'_, Box<dyn Transport + Send>> {
+        //a panic mid command at worst leaves a dead socket, which reconnects
+        self.connection
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.transport().is_connected()
+    }
+
+    pub fn disconnect(&self) {
+        self.transport().disconnect();
+    }
+
+    pub fn get_children(&self) -> Result<Vec<KasaChildren>> {
+        let mut transport = self.transport();
+        match self.protocol {
+            Protocol::Kasa => kasa_protocol::get_children(&mut *transport),
+            #[cfg(feature = "smart")]
+            Protocol::Smart => smart_protocol::get_children(&mut *transport),
         }
-        //let children = self.kasa_info.system.unwrap().get_sysinfo.unwrap().children.clone();
-        println!("failed to get children");
-        return None;
     }
 
-    pub fn get_realtime(&self) -> Option<Vec<Realtime>> {
-        let stream = TcpStream::connect(self.ip_addr.clone());
+    pub fn get_realtime(&self) -> Result<Vec<Realtime>> {
+        if self.protocol != Protocol::Kasa {
+            return Err(KasaError::Unsupported(
+                "realtime is only available on kasa devices".to_string(),
+            ));
+        }
+        kasa_protocol::get_all_realtime(&mut *self.transport())
+    }
 
-        if let Ok(mut strm) = stream {
-            let realtime = match kasa_protocol::get_all_realtime(&mut strm) {
-                Ok(rt) => rt,
-                Err(_err) => vec![],
-            };
-            return Some(realtime);
+    //several kasa calls in one round trip, see command::Batch
+    pub fn execute_batch(&self, batch: &Batch) -> Result<BatchResponse> {
+        if self.protocol != Protocol::Kasa {
+            return Err(KasaError::Unsupported(
+                "batched queries are only available on kasa devices".to_string(),
+            ));
         }
-        //let children = self.kasa_info.system.unwrap().get_sysinfo.unwrap().children.clone();
-        println!("failed to get realtime");
-        return None;
+        kasa_protocol::execute_batch(&mut *self.transport(), batch)
+    }
+
+    //re-reads sysinfo, the cloud binding and, where the device has an emeter,
+    //realtime in a single request. modules the device lacks are left empty
+    pub fn refresh(&mut self) -> Result<()> {
+        let sysinfo = Command::get_sysinfo();
+        let realtime = Command::get_realtime();
+        let cloud = Command::get_cloud_info();
+        let resp = self.execute_batch(
+            &Batch::new()
+                .with(sysinfo.clone())
+                .with(realtime.clone())
+                .with(cloud.clone()),
+        )?;
+        self.kasa_info.system = Some(System {
+            get_sysinfo: Some(resp.get(&sysinfo)?),
+        });
+        self.realtime = resp.get(&realtime).map(|rt| vec![rt]).unwrap_or_default();
+        self.kasa_info.cloud = Some(CnCloud {
+            get_info: resp.get(&cloud).ok(),
+        });
+        Ok(())
+    }
+
+    #[cfg(feature = "smart")]
+    pub fn smart_info(&self) -> Result<SmartDeviceInfo> {
+        smart_protocol::get_device_info(&mut *self.transport())
+    }
+
+    #[cfg(feature = "smart")]
+    pub fn energy_usage(&self) -> Result<EnergyUsage> {
+        smart_protocol::get_energy_usage(&mut *self.transport())
     }
 
     pub fn sysinfo_raw(&self) -> Option<String> {
-        Some(serde_json::to_string(&self.kasa_info.system.clone()?.get_sysinfo?).unwrap())
+        serde_json::to_string(&self.kasa_info.system.clone()?.get_sysinfo?).ok()
     }
 
     pub fn sysinfo(&self) -> Option<SysInfo> {
-        Some(self.kasa_info.system.clone()?.get_sysinfo?)
+        self.kasa_info.system.clone()?.get_sysinfo
+    }
+
+    //the cloud binding as of the last refresh
+    pub fn cloud_info(&self) -> Option<CloudInfo> {
+        self.kasa_info.cloud.clone()?.get_info
     }
 
     pub fn children(&self) -> Option<Vec<KasaChildren>> {
@@ -70,87 +289,164 @@ impl Device {
         Some(self.sysinfo()?.child_num)
     }
     pub fn has_children(&self) -> bool {
-        if let Some(nc) = self.num_children() {
-            if nc > 0 {
-                return true;
-            }
-        }
-        return false;
+        matches!(self.num_children(), Some(nc) if nc > 0)
     }
     pub fn realtime(&self) -> Vec<Realtime> {
         self.realtime.clone()
     }
 
-    pub fn toggle_relay_by_id(&self, idx: usize) {
-        println!("ip:{:}, idx: {:}", self.ip_addr, idx);
-        let stream = TcpStream::connect(self.ip_addr.clone());
-        if let Ok(mut strm) = stream {
-            let _ = toggle_relay_by_idx(&mut strm, idx);
-            println!("toggl'd");
+    pub fn toggle_relay_by_id(&self, idx: usize) -> Result<()> {
+        let mut transport = self.transport();
+        match self.protocol {
+            Protocol::Kasa => toggle_relay_by_idx(&mut *transport, idx),
+            #[cfg(feature = "smart")]
+            Protocol::Smart => smart_protocol::toggle_relay_by_idx(&mut *transport, idx),
         }
     }
+
     //similar to kasa protocol but wont retrieve a new sysinfo first
-    pub fn set_child_relay_by_idx(&self, idx: usize, state: u8) { 
-        println!("{:}", state);
-        if let Ok(mut stream) = TcpStream::connect(self.ip_addr.clone()) {
-            if let Some(children) = self.children() {
-                if idx < children.len() {
-                    let child_id = &children[idx].id;
-                    println!("{:}", child_id);
-                    let _result = set_relay_by_idx(&mut stream, idx, state);
-                    if let Ok(res) = _result {
-                        println!("{:}", res);
-                    }
-                    println!("toggling");
+    pub fn set_child_relay_by_idx(&self, idx: usize, state: u8) -> Result<()> {
+        let mut transport = self.transport();
+        match self.protocol {
+            Protocol::Kasa => {
+                let count = self.children().map_or(0, |children| children.len());
+                if idx >= count {
+                    return Err(KasaError::InvalidChildIndex {
+                        idx,
+                        num_children: count,
+                    });
                 }
+                set_relay_by_idx(&mut *transport, idx, state)
             }
+            #[cfg(feature = "smart")]
+            Protocol::Smart => smart_protocol::set_relay_by_idx(&mut *transport, idx, state),
         }
     }
 
-    pub fn set_single_relay(&self, state: u8) {
-        if let Ok(mut stream) = TcpStream::connect(self.ip_addr.clone()) {
-            let _ = set_single_relay_outlet(&mut stream, state);
+    pub fn set_single_relay(&self, state: u8) -> Result<()> {
+        let mut transport = self.transport();
+        match self.protocol {
+            Protocol::Kasa => set_single_relay_outlet(&mut *transport, state),
+            #[cfg(feature = "smart")]
+            Protocol::Smart => smart_protocol::set_device_on(&mut *transport, state != 0),
         }
     }
 
-    pub fn toggle_single_relay(&self) {
-        if let Ok(mut stream) = TcpStream::connect(self.ip_addr.clone()) {
-            let _ = toggle_single_relay_outlet(&mut stream);
+    pub fn toggle_single_relay(&self) -> Result<()> {
+        let mut transport = self.transport();
+        match self.protocol {
+            Protocol::Kasa => toggle_single_relay_outlet(&mut *transport),
+            #[cfg(feature = "smart")]
+            Protocol::Smart => smart_protocol::toggle_single_relay(&mut *transport),
+        }
+    }
+
+    //the system module calls below are kasa only
+    fn kasa_transport(&self, call: &str) -> Result<MutexGuard<'_, Box<dyn Transport + Send>>> {
+        if self.protocol != Protocol::Kasa {
+            return Err(KasaError::Unsupported(format!(
+                "{call} is only available on kasa devices"
+            )));
+        }
+        Ok(self.transport())
+    }
+
+    pub fn reboot(&self, delay: u32) -> Result<()> {
+        kasa_protocol::reboot(&mut **self.kasa_transport("reboot")?, delay)
+    }
+
+    pub fn factory_reset(&self, delay: u32) -> Result<()> {
+        kasa_protocol::factory_reset(&mut **self.kasa_transport("reset")?, delay)
+    }
+
+    pub fn set_led_off(&self, off: bool) -> Result<()> {
+        kasa_protocol::set_led_off(&mut **self.kasa_transport("set_led_off")?, off)
+    }
+
+    pub fn set_location(&self, latitude: f64, longitude: f64) -> Result<()> {
+        let mut transport = self.kasa_transport("set_dev_location")?;
+        kasa_protocol::set_dev_location(&mut **transport, latitude, longitude)
+    }
+
+    pub fn set_alias(&self, alias: &str) -> Result<()> {
+        kasa_protocol::set_dev_alias(&mut **self.kasa_transport("set_dev_alias")?, alias)
+    }
+
+    pub fn set_child_alias(&self, child_id: &str, alias: &str) -> Result<()> {
+        let mut transport = self.kasa_transport("set_dev_alias")?;
+        kasa_protocol::set_outlet_alias(&mut **transport, child_id, alias)
+    }
+
+    //the time module is kasa only too, time is local to the new zone
+    pub fn set_timezone(&self, index: u8, time: &DeviceTime) -> Result<()> {
+        kasa_protocol::set_timezone(&mut **self.kasa_transport("set_timezone")?, index, time)
+    }
+}
+
+fn on_off(state: u8) -> &'static str {
+    match state {
+        0 => "off",
+        _ => "on",
+    }
+}
+
+//a short human readable summary, what the cli prints for a device
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.sysinfo() {
+            Some(si) => {
+                writeln!(f, "{} ({}) at {}", si.alias, si.model, self.ip_addr)?;
+                if si.children.is_empty() {
+                    writeln!(f, "  relay: {}", on_off(si.relay_state))?;
+                }
+                for (idx, child) in si.children.iter().enumerate() {
+                    writeln!(f, "  [{idx}] {}: {}", child.alias, on_off(child.state))?;
+                }
+            }
+            None => writeln!(f, "device at {}", self.ip_addr)?,
+        }
+        match self.cloud_info() {
+            Some(cloud) if cloud.is_bound() => write!(
+                f,
+                "  cloud: bound to {} on {} ({})",
+                cloud.username,
+                cloud.server,
+                match cloud.is_connected() {
+                    true => "connected",
+                    false => "not connected",
+                }
+            ),
+            Some(_) => write!(f, "  cloud: not bound"),
+            None => write!(f, "  cloud: unknown"),
         }
     }
 }
 
 pub fn determine_target(t_addr: String) -> Result<Device> {
-    if t_addr == "" {
-        return Err(anyhow!("Discovery failed and no target was provided"));
+    if t_addr.is_empty() {
+        Err(KasaError::InvalidAddress(
+            "Discovery failed and no target was provided".to_string(),
+        ))
         //try discovery
         //if let Ok(kd) = discover() {
         //    return Ok(kd);
         //} else {
         //    return Err(anyhow!("Discovery failed and no target was provided"));
         //}
-    } else {
-        if validate_ip(&t_addr) {
-            println!("good ip");
-            let mut stream = TcpStream::connect(t_addr.clone() + ":9999")?;
-            return match get_sys_info(&mut stream) {
-                Ok(si) => Ok(Device {
-                    ip_addr: t_addr,
-                    kasa_info: KasaResp {
-                        system: Some(System {
-                            get_sysinfo: Some(si),
-                        }),
-                        emeter: None,
-                    },
-                    realtime: vec![],
+    } else if validate_ip(&t_addr) {
+        let si = get_sys_info(&mut TcpTransport::new(&t_addr, ConnectionConfig::default()))?;
+        Ok(Device::new(
+            t_addr,
+            KasaResp {
+                system: Some(System {
+                    get_sysinfo: Some(si),
                 }),
-                Err(si) => Err(si),
-            };
-        } else {
-            println!("bad ip");
-
-            return Err(anyhow!("Provided ip is invalid"));
-        }
+                emeter: None,
+                cloud: None,
+            },
+        ))
+    } else {
+        Err(KasaError::InvalidAddress(t_addr))
     }
 }
 
@@ -212,24 +508,22 @@ pub fn discover() -> Result<Vec<Device>> {
         match socket.recv_from(&mut buf) {
             Ok((amt, addr)) => {
                 let ip_addr = addr.to_string();
-                let mut recv: Vec<u8> = vec![];
-                recv.extend_from_slice(&buf[..amt]);
-                let info = deserialize(&decrypt(&recv));
-                devices.push(Device::new(ip_addr, info));
+                //a garbled reply from one device shouldn't sink the whole scan
+                if let Ok(info) = deserialize(&decrypt(&buf[..amt])) {
+                    devices.push(Device::new(ip_addr, info));
+                }
                 buf = [0; 2048];
             }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                //println!("Timed out");
-                break;
-            }
-            Err(_) => {
-                println!("something else");
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
                 break;
             }
+            Err(err) => return Err(err.into()),
         }
     }
 
-    return Ok(devices);
+    Ok(devices)
 }
 
 //Lower overhead version for embedded usecases.
@@ -252,20 +546,148 @@ pub fn discover_ip() -> Result<Vec<String>> {
     loop {
         match socket.recv_from(&mut buf) {
             Ok((_amt, addr)) => {
-                
                 let ip_addr = addr.ip().to_string();
                 devices.push(ip_addr);
             }
-            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
-                //println!("Timed out");
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
                 break;
             }
-            Err(_) => {
-                println!("something else");
+            Err(err) => return Err(err.into()),
+        }
+    }
+
+    Ok(devices)
+}
+
+//the query newer devices listen for on 20002. a fixed 16 byte header
+//(version 2, op 1, no payload) with its checksum already filled in
+const DISCOVERY_QUERY_20002: [u8; 16] = [
+    0x02, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x46, 0x3c, 0xb5, 0xd3,
+];
+const DISCOVERY_HEADER_LEN: usize = 16;
+
+//how a discovered device has to be talked to
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceTransport {
+    //length prefixed xor on tcp 9999, what Device and kasa_protocol use
+    Xor,
+    //klap over http, see klap::KlapTransport
+    Klap { port: u16, login_version: u8 },
+    //the older aes "securePassthrough" handshake over http
+    Aes { port: u16, https: bool },
+    //an encrypt_type we don't know about yet
+    Unknown(String),
+}
+
+impl DeviceTransport {
+    fn from_discovery(result: &DiscoveryResult) -> DeviceTransport {
+        let scheme = &result.mgt_encrypt_schm;
+        let port = scheme.http_port.unwrap_or(80);
+        match scheme.encrypt_type.to_ascii_uppercase().as_str() {
+            "KLAP" => DeviceTransport::Klap {
+                port,
+                login_version: scheme.lv.unwrap_or(1),
+            },
+            "AES" => DeviceTransport::Aes {
+                port,
+                https: scheme.is_support_https,
+            },
+            other => DeviceTransport::Unknown(other.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DiscoveredDevice {
+    //bare ip, the port depends on the transport
+    pub ip_addr: String,
+    pub transport: DeviceTransport,
+    //filled in for xor devices, which answer discovery with their sysinfo
+    pub sysinfo: Option<SysInfo>,
+    //filled in for devices that answered on 20002
+    pub discovery: Option<DiscoveryResult>,
+}
+
+impl DiscoveredDevice {
+    pub fn model(&self) -> Option<&str> {
+        match (&self.sysinfo, &self.discovery) {
+            (Some(sysinfo), _) => Some(&sysinfo.model),
+            (None, Some(discovery)) => Some(&discovery.device_model),
+            (None, None) => None,
+        }
+    }
+
+    pub fn mac(&self) -> Option<&str> {
+        match (&self.sysinfo, &self.discovery) {
+            (Some(sysinfo), _) => Some(&sysinfo.mac),
+            (None, Some(discovery)) => Some(&discovery.mac),
+            (None, None) => None,
+        }
+    }
+}
+
+fn parse_discovery_20002(buf: &[u8]) -> Option<DiscoveryResult> {
+    let payload = buf.get(DISCOVERY_HEADER_LEN..)?;
+    let resp: DiscoveryResp = serde_json::from_slice(payload).ok()?;
+    (resp.error_code == 0).then_some(resp.result)
+}
+
+//broadcasts both the legacy xor query on 9999 and the newer query on 20002,
+//reporting every device that answers along with the transport it needs.
+//a device answering both is reported once, preferring the xor reply
+pub fn discover_all(wait: Duration) -> Result<Vec<DiscoveredDevice>> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_broadcast(true)?;
+    socket.set_read_timeout(Some(wait))?;
+
+    let cmd = json!({"system": {"get_sysinfo":0}}).to_string();
+    socket.send_to(&encrypt(&cmd, false), "255.255.255.255:9999")?;
+    socket.send_to(&DISCOVERY_QUERY_20002, "255.255.255.255:20002")?;
+
+    let mut buf = [0; 4096];
+    let mut devices: Vec<DiscoveredDevice> = vec![];
+
+    loop {
+        let (amt, addr) = match socket.recv_from(&mut buf) {
+            Ok(recv) => recv,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::WouldBlock || e.kind() == io::ErrorKind::TimedOut =>
+            {
                 break;
             }
+            Err(err) => return Err(err.into()),
+        };
+        let ip_addr = addr.ip().to_string();
+
+        let found = match addr.port() {
+            9999 => deserialize(&decrypt(&buf[..amt]))
+                .ok()
+                .and_then(|info| info.system?.get_sysinfo)
+                .map(|sysinfo| DiscoveredDevice {
+                    ip_addr: ip_addr.clone(),
+                    transport: DeviceTransport::Xor,
+                    sysinfo: Some(sysinfo),
+                    discovery: None,
+                }),
+            20002 => parse_discovery_20002(&buf[..amt]).map(|result| DiscoveredDevice {
+                ip_addr: ip_addr.clone(),
+                transport: DeviceTransport::from_discovery(&result),
+                sysinfo: None,
+                discovery: Some(result),
+            }),
+            _ => None,
+        };
+
+        if let Some(found) = found {
+            match devices.iter_mut().find(|dev| dev.ip_addr == ip_addr) {
+                Some(existing) if found.transport == DeviceTransport::Xor => *existing = found,
+                Some(_) => {}
+                None => devices.push(found),
+            }
         }
     }
 
-    return Ok(devices);
+    Ok(devices)
 }
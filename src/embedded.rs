@@ -0,0 +1,160 @@
+//allocation free command functions built on codec and fixed_models.
+//the caller brings the socket (anything implementing embedded-io Read + Write,
+//e.g. an esp-idf or embassy tcp socket) and a buffer big enough for a full frame
+use core::fmt::{self, Write as _};
+use embedded_io::{Read, ReadExactError, Write};
+use heapless::String;
+
+use crate::codec::{self, CodecError};
+use crate::fixed_models::{KasaResp, Realtime, SysInfo};
+
+//requests are short, the longest is a relay command with a child context
+const REQUEST_LEN: usize = 128;
+//scratch space for unescaping json strings, enough for the longest bounded field
+const UNESCAPE_LEN: usize = 64;
+
+#[derive(Debug)]
+pub enum EmbeddedError<E> {
+    Io(E),
+    //the peer closed the connection mid frame
+    UnexpectedEof,
+    Codec(CodecError),
+    Json(serde_json_core::de::Error),
+    //the request didn't fit in REQUEST_LEN
+    RequestTooLong,
+    Device { err_code: i32 },
+    MissingField(&'static str),
+}
+
+impl<E: fmt::Debug> fmt::Display for EmbeddedError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmbeddedError::Io(err) => write!(f, "io error: {err:?}"),
+            EmbeddedError::UnexpectedEof => write!(f, "connection closed mid frame"),
+            EmbeddedError::Codec(err) => write!(f, "framing error: {err}"),
+            EmbeddedError::Json(err) => write!(f, "json decode error: {err}"),
+            EmbeddedError::RequestTooLong => write!(f, "request exceeds {REQUEST_LEN} bytes"),
+            EmbeddedError::Device { err_code } => write!(f, "device error {err_code}"),
+            EmbeddedError::MissingField(field) => write!(f, "response is missing {field}"),
+        }
+    }
+}
+
+impl<E> From<CodecError> for EmbeddedError<E> {
+    fn from(err: CodecError) -> EmbeddedError<E> {
+        EmbeddedError::Codec(err)
+    }
+}
+
+impl<E> From<serde_json_core::de::Error> for EmbeddedError<E> {
+    fn from(err: serde_json_core::de::Error) -> EmbeddedError<E> {
+        EmbeddedError::Json(err)
+    }
+}
+
+impl<E> From<ReadExactError<E>> for EmbeddedError<E> {
+    fn from(err: ReadExactError<E>) -> EmbeddedError<E> {
+        match err {
+            ReadExactError::UnexpectedEof => EmbeddedError::UnexpectedEof,
+            ReadExactError::Other(err) => EmbeddedError::Io(err),
+        }
+    }
+}
+
+type Result<T, S> = core::result::Result<T, EmbeddedError<<S as embedded_io::ErrorType>::Error>>;
+
+fn check_err_code<E>(err_code: i32) -> core::result::Result<(), EmbeddedError<E>> {
+    match err_code {
+        0 => Ok(()),
+        err_code => Err(EmbeddedError::Device { err_code }),
+    }
+}
+
+//encodes `request` into `buf`, sends it, then reads the reply back into `buf`
+//and decodes it in place. returns the plaintext json
+fn send_and_read<'b, S: Read + Write>(
+    stream: &mut S,
+    request: &str,
+    buf: &'b mut [u8],
+) -> Result<&'b [u8], S> {
+    let len = codec::encode_frame(request.as_bytes(), buf)?;
+    stream.write_all(&buf[..len]).map_err(EmbeddedError::Io)?;
+
+    let mut header = [0u8; codec::HEADER_LEN];
+    stream.read_exact(&mut header)?;
+    let len = codec::decode_header(header);
+    if len > buf.len() {
+        return Err(EmbeddedError::Codec(CodecError::BufferTooSmall {
+            needed: len,
+            available: buf.len(),
+        }));
+    }
+    let payload = &mut buf[..len];
+    stream.read_exact(payload)?;
+    codec::decode_in_place(payload);
+    Ok(payload)
+}
+
+fn query<S: Read + Write>(stream: &mut S, request: &str, buf: &mut [u8]) -> Result<KasaResp, S> {
+    let payload = send_and_read(stream, request, buf)?;
+    let mut unescape = [0u8; UNESCAPE_LEN];
+    let (resp, _) = serde_json_core::from_slice_escaped(payload, &mut unescape)?;
+    Ok(resp)
+}
+
+fn format_request<E>(
+    args: fmt::Arguments,
+) -> core::result::Result<String<REQUEST_LEN>, EmbeddedError<E>> {
+    let mut request: String<REQUEST_LEN> = String::new();
+    request
+        .write_fmt(args)
+        .map_err(|_| EmbeddedError::RequestTooLong)?;
+    Ok(request)
+}
+
+pub fn get_sys_info<S: Read + Write>(stream: &mut S, buf: &mut [u8]) -> Result<SysInfo, S> {
+    let resp = query(stream, r#"{"system":{"get_sysinfo":null}}"#, buf)?;
+    let system = resp.system.ok_or(EmbeddedError::MissingField("system"))?;
+    check_err_code(system.err_code)?;
+    let sys_info = system
+        .get_sysinfo
+        .ok_or(EmbeddedError::MissingField("system.get_sysinfo"))?;
+    check_err_code(sys_info.err_code)?;
+    Ok(sys_info)
+}
+
+pub fn get_realtime_by_id<S: Read + Write>(
+    stream: &mut S,
+    id: &str,
+    buf: &mut [u8],
+) -> Result<Realtime, S> {
+    let request = format_request(format_args!(
+        r#"{{"context":{{"child_ids":["{id}"]}},"emeter":{{"get_realtime":null}}}}"#
+    ))?;
+    let resp = query(stream, &request, buf)?;
+    let emeter = resp.emeter.ok_or(EmbeddedError::MissingField("emeter"))?;
+    check_err_code(emeter.err_code)?;
+    let rt = emeter
+        .get_realtime
+        .ok_or(EmbeddedError::MissingField("emeter.get_realtime"))?;
+    check_err_code(rt.err_code)?;
+    Ok(rt)
+}
+
+pub fn set_relay_by_child_id<S: Read + Write>(
+    stream: &mut S,
+    child_id: &str,
+    state: u8,
+    buf: &mut [u8],
+) -> Result<(), S> {
+    let request = format_request(format_args!(
+        r#"{{"context":{{"child_ids":["{child_id}"]}},"system":{{"set_relay_state":{{"state":{state}}}}}}}"#
+    ))?;
+    let resp = query(stream, &request, buf)?;
+    let system = resp.system.ok_or(EmbeddedError::MissingField("system"))?;
+    check_err_code(system.err_code)?;
+    let set = system
+        .set_relay_state
+        .ok_or(EmbeddedError::MissingField("system.set_relay_state"))?;
+    check_err_code(set.err_code)
+}
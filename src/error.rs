@@ -0,0 +1,129 @@
+use std::fmt;
+use std::io;
+
+use crate::codec::CodecError;
+
+pub type Result<T> = std::result::Result<T, KasaError>;
+
+#[derive(Debug)]
+pub enum KasaError {
+    Io(io::Error),
+    Timeout,
+    //the connection closed before a whole frame arrived. expected is the
+    //header length while waiting on the prefix, the payload length after it
+    Truncated {
+        expected: usize,
+        received: usize,
+    },
+    //the length prefix claims more than we're willing to buffer
+    FrameTooLarge {
+        len: usize,
+        max: usize,
+    },
+    Json(serde_json::Error),
+    //the device answered but reported an error for the call,
+    //e.g. -1 "module not support" or -2 "method not support"
+    Device {
+        err_code: i64,
+        err_msg: Option<String>,
+    },
+    InvalidChildIndex {
+        idx: usize,
+        num_children: usize,
+    },
+    //the reply parsed but didn't contain the module/method we asked for
+    MissingField(String),
+    InvalidAddress(String),
+    //unexpected status or malformed reply from one of the http based transports
+    Http(String),
+    //the device rejected our credentials during a handshake
+    Authentication(String),
+    //a payload failed to decrypt or its signature didn't check out
+    Crypto(String),
+    //the device needs something this build doesn't support, e.g. a transport
+    //whose cargo feature is disabled
+    Unsupported(String),
+    //commands that can't be combined into one request
+    InvalidBatch(String),
+}
+
+impl fmt::Display for KasaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KasaError::Io(err) => write!(f, "io error: {err}"),
+            KasaError::Timeout => write!(f, "timed out waiting for device"),
+            KasaError::Truncated { expected, received } => {
+                write!(f, "connection closed after {received} of {expected} bytes")
+            }
+            KasaError::FrameTooLarge { len, max } => {
+                write!(f, "frame of {len} bytes exceeds the {max} byte limit")
+            }
+            KasaError::Json(err) => write!(f, "json decode error: {err}"),
+            KasaError::Device {
+                err_code,
+                err_msg: Some(msg),
+            } => write!(f, "device error {err_code}: {msg}"),
+            KasaError::Device {
+                err_code,
+                err_msg: None,
+            } => write!(f, "device error {err_code}"),
+            KasaError::InvalidChildIndex { idx, num_children } => {
+                write!(
+                    f,
+                    "invalid child idx: {idx} where n children: {num_children}"
+                )
+            }
+            KasaError::MissingField(field) => write!(f, "response is missing {field}"),
+            KasaError::InvalidAddress(addr) => write!(f, "invalid address: {addr}"),
+            KasaError::Http(msg) => write!(f, "http error: {msg}"),
+            KasaError::Authentication(msg) => write!(f, "authentication failed: {msg}"),
+            KasaError::Crypto(msg) => write!(f, "crypto error: {msg}"),
+            KasaError::Unsupported(msg) => write!(f, "unsupported: {msg}"),
+            KasaError::InvalidBatch(msg) => write!(f, "invalid batch: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for KasaError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            KasaError::Io(err) => Some(err),
+            KasaError::Json(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for KasaError {
+    fn from(err: io::Error) -> KasaError {
+        match err.kind() {
+            //read/write timeouts surface as either of these depending on platform
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => KasaError::Timeout,
+            _ => KasaError::Io(err),
+        }
+    }
+}
+
+impl From<serde_json::Error> for KasaError {
+    fn from(err: serde_json::Error) -> KasaError {
+        KasaError::Json(err)
+    }
+}
+
+impl From<CodecError> for KasaError {
+    fn from(err: CodecError) -> KasaError {
+        match err {
+            CodecError::BufferTooSmall { needed, available } => KasaError::FrameTooLarge {
+                len: needed,
+                max: available,
+            },
+            CodecError::Truncated {
+                expected,
+                available,
+            } => KasaError::Truncated {
+                expected,
+                received: available,
+            },
+        }
+    }
+}
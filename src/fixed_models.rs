@@ -0,0 +1,79 @@
+//fixed capacity mirrors of the types in models, for targets without an allocator.
+//these deserialize with serde-json-core, which skips any fields not listed here
+use heapless::{String, Vec};
+use serde::{Deserialize, Serialize};
+
+pub use crate::realtime::{MissingReading, Realtime};
+
+//an HS300 has six outlets, nothing else in the lineup has more
+pub const MAX_CHILDREN: usize = 6;
+pub const ALIAS_LEN: usize = 32;
+//child ids are the 40 char deviceId with a two digit outlet index appended
+pub const ID_LEN: usize = 42;
+pub const FIELD_LEN: usize = 32;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default)]
+pub struct NextAction {
+    pub r#type: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct KasaChildren {
+    pub id: String<ID_LEN>,
+    pub state: u8,
+    pub alias: String<ALIAS_LEN>,
+    pub on_time: u64,
+    #[serde(default)]
+    pub next_action: NextAction,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)] //kasa json mixes snake and camel and I don't have control of that
+pub struct SysInfo {
+    pub alias: String<ALIAS_LEN>,
+    #[serde(default)]
+    pub child_num: usize,
+    #[serde(default)]
+    pub children: Vec<KasaChildren, MAX_CHILDREN>,
+    pub deviceId: String<ID_LEN>,
+    pub err_code: i32,
+    pub hw_ver: String<FIELD_LEN>,
+    pub led_off: u8,
+    pub mac: String<FIELD_LEN>,
+    pub model: String<FIELD_LEN>,
+    #[serde(default)]
+    pub on_time: u32,
+    #[serde(default)]
+    pub relay_state: u8,
+    pub rssi: i32,
+    pub sw_ver: String<FIELD_LEN>,
+}
+
+//a bare {"err_code":0} as returned by set_* calls,
+//or a module that doesn't exist on the device
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+pub struct ErrCode {
+    #[serde(default)]
+    pub err_code: i32,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct System {
+    #[serde(default)]
+    pub err_code: i32,
+    pub get_sysinfo: Option<SysInfo>,
+    pub set_relay_state: Option<ErrCode>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Emeter {
+    #[serde(default)]
+    pub err_code: i32,
+    pub get_realtime: Option<Realtime>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct KasaResp {
+    pub system: Option<System>,
+    pub emeter: Option<Emeter>,
+}
@@ -0,0 +1,143 @@
+//just enough http/1.1 to POST a body at a device and read the reply.
+//the newer transports (klap and the tapo passthrough) all work this way,
+//and pulling in a full http client isn't worth it for two endpoints
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::TcpStream;
+
+use crate::error::{KasaError, Result};
+use crate::transport::{resolve_addr_with_port, ConnectionConfig};
+
+pub(crate) struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    //value of a cookie from the Set-Cookie header, e.g. TP_SESSIONID
+    pub fn cookie(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .filter(|(key, _)| key.eq_ignore_ascii_case("set-cookie"))
+            .flat_map(|(_, value)| value.split(';'))
+            .filter_map(|pair| pair.trim().split_once('='))
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value)
+    }
+}
+
+//one request per connection, the devices don't do keep-alive reliably
+pub(crate) fn post(
+    addr: &str,
+    default_port: u16,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    config: &ConnectionConfig,
+) -> Result<HttpResponse> {
+    let sock_addr = resolve_addr_with_port(addr, default_port)?;
+    let mut stream = TcpStream::connect_timeout(&sock_addr, config.connect_timeout)?;
+    stream.set_read_timeout(config.read_timeout)?;
+    stream.set_write_timeout(config.write_timeout)?;
+
+    let mut request = format!(
+        "POST {path} HTTP/1.1\r\nHost: {sock_addr}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        body.len()
+    );
+    for (key, value) in headers {
+        request.push_str(&format!("{key}: {value}\r\n"));
+    }
+    request.push_str("\r\n");
+
+    let mut out = request.into_bytes();
+    out.extend_from_slice(body);
+    stream.write_all(&out)?;
+
+    //read the head and then exactly Content-Length bytes, rather than
+    //waiting on the device to close its end after replying
+    let mut reader = BufReader::new(stream);
+    let head = read_head(&mut reader, config.max_frame_len)?;
+    let (status, headers) = parse_head(&head)?;
+    let content_len = headers
+        .iter()
+        .find(|(key, _)| key.eq_ignore_ascii_case("content-length"))
+        .and_then(|(_, value)| value.parse::<usize>().ok());
+    let body = read_body(&mut reader, content_len, config.max_frame_len)?;
+
+    Ok(HttpResponse {
+        status,
+        headers,
+        body,
+    })
+}
+
+//the status line and headers, up to and excluding the blank line
+fn read_head<R: BufRead>(reader: &mut R, max_len: usize) -> Result<String> {
+    let mut head = vec![];
+    loop {
+        let line_start = head.len();
+        let read = reader.read_until(b'\n', &mut head)?;
+        if read == 0 {
+            return Err(KasaError::Truncated {
+                expected: head.len() + 2,
+                received: head.len(),
+            });
+        }
+        if &head[line_start..] == b"\r\n" {
+            head.truncate(line_start);
+            return Ok(String::from_utf8_lossy(&head).into_owned());
+        }
+        if head.len() > max_len {
+            return Err(KasaError::FrameTooLarge {
+                len: head.len(),
+                max: max_len,
+            });
+        }
+    }
+}
+
+fn parse_head(head: &str) -> Result<(u16, Vec<(String, String)>)> {
+    let mut lines = head.split("\r\n");
+
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| KasaError::Http(format!("bad status line in {head:?}")))?;
+
+    let headers = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+        .collect();
+    Ok((status, headers))
+}
+
+//exactly len bytes when the reply says how long it is, otherwise whatever
+//comes before the device closes the connection
+fn read_body<R: Read>(reader: &mut R, len: Option<usize>, max_len: usize) -> Result<Vec<u8>> {
+    let mut body = vec![];
+    match len {
+        Some(len) if len > max_len => Err(KasaError::FrameTooLarge { len, max: max_len }),
+        Some(len) => {
+            reader.take(len as u64).read_to_end(&mut body)?;
+            if body.len() < len {
+                return Err(KasaError::Truncated {
+                    expected: len,
+                    received: body.len(),
+                });
+            }
+            Ok(body)
+        }
+        None => {
+            reader.take(max_len as u64 + 1).read_to_end(&mut body)?;
+            if body.len() > max_len {
+                return Err(KasaError::FrameTooLarge {
+                    len: body.len(),
+                    max: max_len,
+                });
+            }
+            Ok(body)
+        }
+    }
+}
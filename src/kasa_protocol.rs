@@ -1,297 +1,632 @@
-use anyhow::{anyhow, Result};
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::io::prelude::*;
-use std::net::TcpStream;
 
-use crate::models::{KasaChildren, KasaResp, Realtime, SysInfo};
+use crate::codec;
+pub use crate::command::check_err_code;
+use crate::command::{Batch, BatchResponse, Command, ANTI_THEFT, COUNTDOWN, SCHEDULE};
+use crate::error::{KasaError, Result};
+use crate::models::{
+    AntiTheftRule, AntiTheftRuleList, CloudInfo, CountdownRule, CountdownRuleList, DayStat,
+    DayStatList, DeviceTime, FirmwareEntry, FirmwareList, Gain, KasaChildren, KasaResp, MonthStat,
+    MonthStatList, Realtime, ScanInfo, ScheduleRule, ScheduleRuleList, SysInfo, Timezone,
+};
+use crate::transport::Transport;
 
-// https://github.com/softScheck/tplink-smartplug/blob/master/tplink_smartplug.py#L70
 pub fn encrypt(input: &str, inc_len: bool) -> Vec<u8> {
-    let mut key = 171; //just the initial key
     let mut result: Vec<u8> = vec![];
 
     //the same xor is used for both discovery and the protocol
     //discovery lacks it, so we'll just switch here
     if inc_len {
-        for octet in (input.len() as u32).to_be_bytes() {
-            result.push(octet);
-        }
+        result.extend_from_slice(&codec::encode_header(input.len()));
     }
 
-    for c in input.bytes() {
-        let a = key ^ c;
-        key = a;
-        result.push(a);
-    }
-    return result;
+    let start = result.len();
+    result.extend_from_slice(input.as_bytes());
+    codec::encode_in_place(&mut result[start..]);
+    result
 }
 
-pub fn decrypt(input: &Vec<u8>) -> String {
-    let mut key = 171;
-    let mut result = String::new();
+pub fn decrypt(input: &[u8]) -> String {
+    let mut result = input.to_vec();
+    codec::decode_in_place(&mut result);
+    String::from_utf8_lossy(&result).into_owned()
+}
+
+pub fn deserialize(input: &str) -> Result<KasaResp> {
+    let s: KasaResp = serde_json::from_str(input)?;
+    Ok(s)
+}
 
-    for c in input {
-        let a = key ^ *c;
-        key = *c;
-        result.push(a as char);
+//sysinfo from a fully populated HS300 is a couple KB, scan and schedule lists
+//can run longer, but nothing the devices send comes near this
+pub const DEFAULT_MAX_FRAME_LEN: usize = 64 * 1024;
+
+//like read_exact, but reports how far it got when the peer hangs up
+fn read_full<R: Read + ?Sized>(stream: &mut R, buf: &mut [u8]) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match stream.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(err) => return Err(err.into()),
+        }
     }
+    Ok(filled)
+}
 
-    return result;
+//reads exactly one length prefixed frame, leaving anything after it on the stream
+pub fn read_kasa_frame<R: Read + ?Sized>(stream: &mut R, max_len: usize) -> Result<Vec<u8>> {
+    let mut header = [0u8; codec::HEADER_LEN];
+    let received = read_full(stream, &mut header)?;
+    if received < header.len() {
+        return Err(KasaError::Truncated {
+            expected: header.len(),
+            received,
+        });
+    }
+    let len = codec::decode_header(header);
+    if len > max_len {
+        return Err(KasaError::FrameTooLarge { len, max: max_len });
+    }
+
+    let mut recv = vec![0u8; len];
+    let received = read_full(stream, &mut recv)?;
+    if received < len {
+        return Err(KasaError::Truncated {
+            expected: len,
+            received,
+        });
+    }
+    Ok(recv)
 }
 
-pub fn deserialize(input: &str) -> KasaResp {
-    let s: KasaResp = serde_json::from_str(input).expect("deserialization failed");
-    return s;
+pub fn read_kasa_resp<R: Read + ?Sized>(stream: &mut R) -> Result<Vec<u8>> {
+    read_kasa_frame(stream, DEFAULT_MAX_FRAME_LEN)
 }
 
-pub fn read_kasa_resp(stream: &mut TcpStream) -> Result<Vec<u8>> {
-    let mut len = [0u8; 4];
-    let _ = stream.read_exact(&mut len); //TODO: add a timeout here and return an option or result
-    let len: usize = u32::from_be_bytes(len).try_into().unwrap();
-    //println!("resp len: {}", len);
+pub fn send_kasa_cmd<W: Write + ?Sized>(stream: &mut W, cmd: &str) -> Result<()> {
+    let cmd = encrypt(cmd, true);
+    stream.write_all(&cmd)?;
+    Ok(())
+}
 
-    let mut recv: Vec<u8> = vec![];
-    let mut rx_bytes = [0u8; 256];
-    loop {
-        let bytes_read = match stream.read(&mut rx_bytes) {
-            Ok(bytes) => bytes,
-            Err(err) => {
-                println!("stream.read failed, err: {:}", err);
-                return Err(anyhow!("stream read error: {:?}", err));
-            }
-        };
+fn send_and_read<T: Transport + ?Sized>(
+    transport: &mut T,
+    cmd: &str,
+    idempotent: bool,
+) -> Result<Value> {
+    let resp = match idempotent {
+        true => transport.query_idempotent(cmd)?,
+        false => transport.query(cmd)?,
+    };
+    Ok(serde_json::from_str(&resp)?)
+}
 
-        recv.extend_from_slice(&rx_bytes[..bytes_read]);
-        if recv.len() >= len {
-            break;
-        }
-    }
-    return Ok(recv);
+//sends one command and hands back its part of the reply. R is whatever the
+//method returns, IgnoredAny for setters where only the err_code matters
+pub fn execute<T: Transport + ?Sized, R: DeserializeOwned>(
+    transport: &mut T,
+    cmd: &Command,
+) -> Result<R> {
+    let resp = send_and_read(transport, &cmd.to_string(), cmd.is_idempotent())?;
+    cmd.parse(&resp)
 }
 
-pub fn send_kasa_cmd(stream: &mut TcpStream, cmd: &str) {
-    let cmd = encrypt(cmd, true);
-    let _ = stream.write(&cmd);
+//sends every command in the batch as a single request
+pub fn execute_batch<T: Transport + ?Sized>(
+    transport: &mut T,
+    batch: &Batch,
+) -> Result<BatchResponse> {
+    let resp = send_and_read(
+        transport,
+        &batch.to_json()?.to_string(),
+        batch.is_idempotent(),
+    )?;
+    Ok(BatchResponse::new(batch, resp))
 }
 
-fn send_and_read(stream: &mut TcpStream, cmd: &str) -> Result<KasaResp> {
-    send_kasa_cmd(stream, &cmd);
-    let resp = read_kasa_resp(stream)?;
-    let resp: KasaResp = deserialize(&decrypt(&resp));
-    return Ok(resp);
+//for calls we don't have a helper for yet. sends the request as is, apart from
+//adding a child context, and returns the whole reply without checking err_code
+pub fn query_raw<T: Transport + ?Sized>(
+    transport: &mut T,
+    request: Value,
+    child_id: Option<&str>,
+) -> Result<Value> {
+    send_and_read(
+        transport,
+        &with_child_context(request, child_id)?.to_string(),
+        false,
+    )
 }
 
-pub fn get_sys_info(stream: &mut TcpStream) -> Result<SysInfo> {
-    let cmd = r#"{"system":{"get_sysinfo":null}}"#;
-    send_kasa_cmd(stream, &cmd);
-    let resp = read_kasa_resp(stream)?;
-    let resp: KasaResp = deserialize(&decrypt(&resp));
-    if let Some(system) = resp.system {
-        if let Some(sys_info) = system.get_sysinfo {
-            return Ok(sys_info);
-        }
+pub(crate) fn with_child_context(mut request: Value, child_id: Option<&str>) -> Result<Value> {
+    if let Some(child_id) = child_id {
+        request
+            .as_object_mut()
+            .ok_or_else(|| {
+                KasaError::Unsupported("a child context needs a json object request".to_string())
+            })?
+            .insert("context".to_string(), json!({ "child_ids": [child_id] }));
     }
-    return Err(anyhow!("failed to get sys_info"));
+    Ok(request)
+}
+
+pub fn get_sys_info<T: Transport + ?Sized>(transport: &mut T) -> Result<SysInfo> {
+    execute(transport, &Command::get_sysinfo())
 }
 
-pub fn get_all_realtime(stream: &mut TcpStream) -> Result<Vec<Realtime>> {
-    let c = get_children(stream)?;
+pub fn get_all_realtime<T: Transport + ?Sized>(transport: &mut T) -> Result<Vec<Realtime>> {
+    let c = get_children(transport)?;
     //you'd think a field with a plural 'ids' in list brackets would accept a list
     // you'd be wrong, so we're calling it multiple times, otherwise it only returns idx0
     let ids: Vec<String> = c.into_iter().map(|x| x.id).collect();
     let mut rts: Vec<Realtime> = vec![];
 
     for id in ids {
-        let resp: KasaResp = send_and_read(
-            stream,
-            &json!({
-                "context" : {
-                    "child_ids" : [ id ]
-            },
-            "emeter": {
-                "get_realtime":null
-            },
-
-            })
-            .to_string(),
-        )?;
-        if let Some(emeter) = resp.emeter {
-            if let Some(rt) = emeter.get_realtime {
-                rts.push(rt)
-            }
-        }
+        rts.push(get_realtime_by_id(transport, &id)?);
     }
 
-    return Ok(rts);
+    Ok(rts)
+}
+
+pub fn get_realtime_by_id<T: Transport + ?Sized>(transport: &mut T, id: &str) -> Result<Realtime> {
+    execute(transport, &Command::get_realtime().child(id))
+}
+
+pub fn get_realtime<T: Transport + ?Sized>(transport: &mut T) -> Result<Realtime> {
+    execute(transport, &Command::get_realtime())
+}
+
+pub fn get_realtime_by_idx<T: Transport + ?Sized>(
+    transport: &mut T,
+    idx: usize,
+) -> Result<Realtime> {
+    let children = get_children(transport)?;
+    let child = child_at(&children, idx)?;
+    get_realtime_by_id(transport, &child.id)
+}
+
+//daily usage for one month. for a strip this is the whole device,
+//use get_daystat_by_id for a single outlet
+pub fn get_daystat<T: Transport + ?Sized>(
+    transport: &mut T,
+    year: u16,
+    month: u8,
+) -> Result<Vec<DayStat>> {
+    let stats: DayStatList = execute(transport, &Command::get_daystat(year, month))?;
+    Ok(stats.day_list)
+}
+
+pub fn get_daystat_by_id<T: Transport + ?Sized>(
+    transport: &mut T,
+    id: &str,
+    year: u16,
+    month: u8,
+) -> Result<Vec<DayStat>> {
+    let stats: DayStatList = execute(transport, &Command::get_daystat(year, month).child(id))?;
+    Ok(stats.day_list)
+}
+
+pub fn get_monthstat<T: Transport + ?Sized>(
+    transport: &mut T,
+    year: u16,
+) -> Result<Vec<MonthStat>> {
+    let stats: MonthStatList = execute(transport, &Command::get_monthstat(year))?;
+    Ok(stats.month_list)
 }
 
-pub fn get_realtime_by_id(stream: &mut TcpStream, id: &String) -> Result<Realtime> {
-    let resp: KasaResp = send_and_read(
-        stream,
-        &json!({
-            "context" : {
-                "child_ids" : [ id ]
-        },
-        "emeter": {
-            "get_realtime":null
-        },
+pub fn get_monthstat_by_id<T: Transport + ?Sized>(
+    transport: &mut T,
+    id: &str,
+    year: u16,
+) -> Result<Vec<MonthStat>> {
+    let stats: MonthStatList = execute(transport, &Command::get_monthstat(year).child(id))?;
+    Ok(stats.month_list)
+}
 
-        })
-        .to_string(),
+//the emeter maintenance calls below take an optional child id, None for a
+//single plug or the strip as a whole
+pub fn erase_emeter_stat<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::erase_emeter_stat().maybe_child(child_id),
     )?;
+    Ok(())
+}
 
-    //let rt = resp.emeter?.get_realtime?;
-    if let Some(emeter) = resp.emeter {
-        if let Some(rt) = emeter.get_realtime {
-            return Ok(rt);
-        }
-    }
-    return Err(anyhow!("Realtime Response is None"));
-}
-
-pub fn get_realtime(stream: &mut TcpStream) -> Result<Realtime> {
-    let resp: KasaResp = send_and_read(
-        stream,
-        &json!({
-        "emeter": {
-            "get_realtime":null
-            },
-        })
-        .to_string(),
+pub fn get_vgain_igain<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<Gain> {
+    execute(transport, &Command::get_vgain_igain().maybe_child(child_id))
+}
+
+pub fn set_vgain<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    vgain: u32,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_vgain(vgain).maybe_child(child_id))?;
+    Ok(())
+}
+
+pub fn set_igain<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    igain: u32,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_igain(igain).maybe_child(child_id))?;
+    Ok(())
+}
+
+//the rule modules (schedule, and friends) share one method set and differ only
+//in the rule type, these do the shared part
+fn get_rules<T: Transport + ?Sized, R: DeserializeOwned>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+) -> Result<R> {
+    execute(transport, &Command::get_rules(module).maybe_child(child_id))
+}
+
+fn add_rule<T: Transport + ?Sized, R: Serialize>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    rule: &R,
+) -> Result<String> {
+    let cmd = Command::add_rule(module, serde_json::to_value(rule)?).maybe_child(child_id);
+    let added: RuleId = execute(transport, &cmd)?;
+    Ok(added.id)
+}
+
+fn edit_rule<T: Transport + ?Sized, R: Serialize>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    rule: &R,
+) -> Result<()> {
+    let cmd = Command::edit_rule(module, serde_json::to_value(rule)?).maybe_child(child_id);
+    execute::<_, IgnoredAny>(transport, &cmd)?;
+    Ok(())
+}
+
+fn delete_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::delete_rule(module, id).maybe_child(child_id),
     )?;
-    if let Some(emeter) = resp.emeter {
-        if let Some(rt) = emeter.get_realtime {
-            return Ok(rt);
-        }
-    }
-    return Err(anyhow!("Realtime response is none"));
+    Ok(())
 }
 
-pub fn get_realtime_by_idx(stream: &mut TcpStream, idx: usize) -> Result<Realtime> {
-    let children = get_children(stream)?;
-    if idx > children.len() {
-        return Err(anyhow!(
-            "invalid idx: {idx} where n children: {}",
-            children.len()
-        ));
-    }
-    get_realtime_by_id(stream, &children[idx].id)
+fn delete_all_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    module: &str,
+    child_id: Option<&str>,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::delete_all_rules(module).maybe_child(child_id),
+    )?;
+    Ok(())
 }
 
-pub fn get_children(stream: &mut TcpStream) -> Result<Vec<KasaChildren>> {
-    let c: Vec<KasaChildren> = get_sys_info(stream)?.children;
-    return Ok(c);
+//the reply to add_rule
+#[derive(Deserialize)]
+pub(crate) struct RuleId {
+    pub id: String,
 }
 
-pub fn toggle_relay_by_alias(_stream: &mut TcpStream, _alias: String) {
-    return;
+pub fn get_schedule_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<ScheduleRuleList> {
+    get_rules(transport, SCHEDULE, child_id)
 }
 
-pub fn toggle_relay_by_idx(stream: &mut TcpStream, idx: usize) -> Result<bool> {
-    let children = get_children(stream).unwrap();
-    if idx < children.len() {
-        let child_id = &children[idx].id;
-        let state = match children[idx].state {
-            0 => 1,
-            _ => 0,
-        };
-        let result = set_relay_by_child_id(stream, &child_id, state);
-        return result;
-    }
-    return Err(anyhow!("Invaid child idx: {}", idx));
+//returns the id the device gave the new rule
+pub fn add_schedule_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &ScheduleRule,
+) -> Result<String> {
+    add_rule(transport, SCHEDULE, child_id, rule)
 }
 
-pub fn set_relay_by_idx(stream: &mut TcpStream, idx: usize, state: u8) -> Result<bool> {
-    let children = get_children(stream).unwrap();
-    if idx < children.len() {
-        let child_id = &children[idx].id;
-        let result = set_relay_by_child_id(stream, &child_id, state);
-        return result;
-    }
-    return Err(anyhow!("Invaid child idx: {}", idx));
+//replaces the rule with the same id, so rule.id has to be set
+pub fn edit_schedule_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &ScheduleRule,
+) -> Result<()> {
+    edit_rule(transport, SCHEDULE, child_id, rule)
+}
+
+pub fn delete_schedule_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    delete_rule(transport, SCHEDULE, child_id, id)
+}
+
+pub fn delete_all_schedule_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, SCHEDULE, child_id)
+}
+
+//turns the whole schedule on or off without touching the rules
+pub fn set_schedule_enabled<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    enable: bool,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::set_overall_enable(SCHEDULE, enable).maybe_child(child_id),
+    )?;
+    Ok(())
+}
+
+pub fn get_countdown_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<CountdownRuleList> {
+    get_rules(transport, COUNTDOWN, child_id)
+}
+
+//the devices keep one countdown per outlet, adding a second fails with
+//an err_code until the first is deleted
+pub fn add_countdown_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &CountdownRule,
+) -> Result<String> {
+    add_rule(transport, COUNTDOWN, child_id, rule)
 }
 
-pub fn toggle_single_relay_outlet(stream: &mut TcpStream) -> Result<bool> {
-    let state = match get_sys_info(stream)?.relay_state {
+pub fn edit_countdown_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &CountdownRule,
+) -> Result<()> {
+    edit_rule(transport, COUNTDOWN, child_id, rule)
+}
+
+pub fn delete_all_countdown_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, COUNTDOWN, child_id)
+}
+
+pub fn get_anti_theft_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<AntiTheftRuleList> {
+    get_rules(transport, ANTI_THEFT, child_id)
+}
+
+pub fn add_anti_theft_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &AntiTheftRule,
+) -> Result<String> {
+    add_rule(transport, ANTI_THEFT, child_id, rule)
+}
+
+pub fn edit_anti_theft_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    rule: &AntiTheftRule,
+) -> Result<()> {
+    edit_rule(transport, ANTI_THEFT, child_id, rule)
+}
+
+pub fn delete_anti_theft_rule<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    id: &str,
+) -> Result<()> {
+    delete_rule(transport, ANTI_THEFT, child_id, id)
+}
+
+pub fn delete_all_anti_theft_rules<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+) -> Result<()> {
+    delete_all_rules(transport, ANTI_THEFT, child_id)
+}
+
+pub fn set_anti_theft_enabled<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: Option<&str>,
+    enable: bool,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(
+        transport,
+        &Command::set_overall_enable(ANTI_THEFT, enable).maybe_child(child_id),
+    )?;
+    Ok(())
+}
+
+pub fn get_time<T: Transport + ?Sized>(transport: &mut T) -> Result<DeviceTime> {
+    execute(transport, &Command::get_time())
+}
+
+pub fn get_timezone<T: Transport + ?Sized>(transport: &mut T) -> Result<Timezone> {
+    execute(transport, &Command::get_timezone())
+}
+
+//index is into timezones::TIMEZONES, time is local to that zone
+pub fn set_timezone<T: Transport + ?Sized>(
+    transport: &mut T,
+    index: u8,
+    time: &DeviceTime,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_timezone(index, time))?;
+    Ok(())
+}
+
+pub fn get_cloud_info<T: Transport + ?Sized>(transport: &mut T) -> Result<CloudInfo> {
+    execute(transport, &Command::get_cloud_info())
+}
+
+pub fn bind_cloud<T: Transport + ?Sized>(
+    transport: &mut T,
+    username: &str,
+    password: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::bind_cloud(username, password))?;
+    Ok(())
+}
+
+//drops the tp-link account binding, the device keeps working locally
+pub fn unbind_cloud<T: Transport + ?Sized>(transport: &mut T) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::unbind_cloud())?;
+    Ok(())
+}
+
+//point the device at another cloud server, e.g. a local stand-in
+pub fn set_cloud_server_url<T: Transport + ?Sized>(transport: &mut T, server: &str) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_cloud_server_url(server))?;
+    Ok(())
+}
+
+//firmware updates the cloud offers, needs a working cloud connection
+pub fn get_intl_fw_list<T: Transport + ?Sized>(transport: &mut T) -> Result<Vec<FirmwareEntry>> {
+    let list: FirmwareList = execute(transport, &Command::get_intl_fw_list())?;
+    Ok(list.fw_list)
+}
+
+//access points the device can see. only useful in ap setup mode, a scan
+//can take a few seconds so allow for it in the read timeout
+pub fn get_scaninfo<T: Transport + ?Sized>(transport: &mut T, refresh: bool) -> Result<ScanInfo> {
+    execute(transport, &Command::get_scaninfo(refresh))
+}
+
+//joins the device to a network. key_type should come from the matching
+//AccessPoint. the device leaves its setup ap straight after, so the reply
+//often never arrives and a Timeout here usually means it worked
+pub fn set_stainfo<T: Transport + ?Sized>(
+    transport: &mut T,
+    ssid: &str,
+    password: &str,
+    key_type: u8,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_stainfo(ssid, password, key_type))?;
+    Ok(())
+}
+
+//reboots after delay seconds, the reply comes back before it goes down
+pub fn reboot<T: Transport + ?Sized>(transport: &mut T, delay: u32) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::reboot(delay))?;
+    Ok(())
+}
+
+//factory reset after delay seconds. wipes wifi, cloud binding, rules and
+//alias, the device comes back in setup mode
+pub fn factory_reset<T: Transport + ?Sized>(transport: &mut T, delay: u32) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::reset(delay))?;
+    Ok(())
+}
+
+pub fn set_led_off<T: Transport + ?Sized>(transport: &mut T, off: bool) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_led_off(off))?;
+    Ok(())
+}
+
+//used for the sunrise/sunset schedule rules
+pub fn set_dev_location<T: Transport + ?Sized>(
+    transport: &mut T,
+    latitude: f64,
+    longitude: f64,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_location(latitude, longitude))?;
+    Ok(())
+}
+
+//the device's own alias, set_outlet_alias is the per outlet one on strips
+pub fn set_dev_alias<T: Transport + ?Sized>(transport: &mut T, alias: &str) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_alias(alias))?;
+    Ok(())
+}
+
+pub fn get_children<T: Transport + ?Sized>(transport: &mut T) -> Result<Vec<KasaChildren>> {
+    let c: Vec<KasaChildren> = get_sys_info(transport)?.children;
+    Ok(c)
+}
+
+pub(crate) fn child_at(children: &[KasaChildren], idx: usize) -> Result<&KasaChildren> {
+    children.get(idx).ok_or(KasaError::InvalidChildIndex {
+        idx,
+        num_children: children.len(),
+    })
+}
+
+pub fn toggle_relay_by_alias<T: Transport + ?Sized>(_transport: &mut T, _alias: String) {}
+
+pub fn toggle_relay_by_idx<T: Transport + ?Sized>(transport: &mut T, idx: usize) -> Result<()> {
+    let children = get_children(transport)?;
+    let child = child_at(&children, idx)?;
+    let state = match child.state {
         0 => 1,
         _ => 0,
     };
-    println!("toggl");
-    set_single_relay_outlet(stream, state)
+    set_relay_by_child_id(transport, &child.id, state)
 }
 
-pub fn set_single_relay_outlet(stream: &mut TcpStream, state: u8) -> Result<bool> {
-    let cmd: String = json!({
-        "system": {
-            "set_relay_state" : {
-                "state" : state
-            }
-        }
-    })
-    .to_string();
-
-    send_kasa_cmd(stream, cmd.as_str());
-    let resp: Value = serde_json::from_str(&decrypt(&read_kasa_resp(stream).unwrap()))?;
-
-    let err_resp = resp["system"]["set_relay_state"]["err_code"]
-        .as_i64()
-        .unwrap();
-
-    return Ok(match err_resp {
-        0 => false,
-        _ => true,
-    });
-}
-
-pub fn set_relay_by_child_id(stream: &mut TcpStream, child_id: &str, state: u8) -> Result<bool> {
-    let cmd: String = json!({
-        "context" : {
-            "child_ids": [ child_id ]
-        },
-        "system": {
-            "set_relay_state" : {
-                "state" : state
-            }
-        }
-    })
-    .to_string();
-
-    send_kasa_cmd(stream, cmd.as_str());
-    let resp: Value = serde_json::from_str(&decrypt(&read_kasa_resp(stream).unwrap()))?;
-
-    let err_resp = resp["system"]["set_relay_state"]["err_code"]
-        .as_i64()
-        .unwrap();
-
-    return Ok(match err_resp {
-        0 => false,
-        _ => true,
-    });
-}
-
-pub fn set_outlet_alias(stream: &mut TcpStream, child_id: &str, alias: &str) -> Result<bool> {
-    let cmd: String = json!({
-        "context" : {
-            "child_ids": [ child_id ]
-        },
-        "system" : {
-            "set_dev_alias":{
-                "alias": alias
-            }
-        }
-    })
-    .to_string();
+pub fn set_relay_by_idx<T: Transport + ?Sized>(
+    transport: &mut T,
+    idx: usize,
+    state: u8,
+) -> Result<()> {
+    let children = get_children(transport)?;
+    let child = child_at(&children, idx)?;
+    set_relay_by_child_id(transport, &child.id, state)
+}
+
+pub fn toggle_single_relay_outlet<T: Transport + ?Sized>(transport: &mut T) -> Result<()> {
+    let state = match get_sys_info(transport)?.relay_state {
+        0 => 1,
+        _ => 0,
+    };
+    set_single_relay_outlet(transport, state)
+}
 
-    send_kasa_cmd(stream, cmd.as_str());
-    let resp: Value = serde_json::from_str(&decrypt(&read_kasa_resp(stream).unwrap()))?;
+pub fn set_single_relay_outlet<T: Transport + ?Sized>(transport: &mut T, state: u8) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_relay_state(state))?;
+    Ok(())
+}
 
-    let err_resp = resp["system"]["set_dev_alias"]["err_code"]
-        .as_i64()
-        .unwrap();
-    println!("{err_resp}");
+pub fn set_relay_by_child_id<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: &str,
+    state: u8,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_relay_state(state).child(child_id))?;
+    Ok(())
+}
 
-    return Ok(match err_resp {
-        0 => false,
-        _ => true,
-    });
+pub fn set_outlet_alias<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: &str,
+    alias: &str,
+) -> Result<()> {
+    execute::<_, IgnoredAny>(transport, &Command::set_dev_alias(alias).child(child_id))?;
+    Ok(())
 }
@@ -0,0 +1,435 @@
+//KLAP, spoken by newer hardware revisions that no longer listen on 9999.
+//json requests are aes-128-cbc encrypted and POSTed to the device over http
+//after a two stage handshake proves both sides know the account credentials.
+// https://github.com/python-kasa/python-kasa/blob/master/kasa/transports/klaptransport.py
+use aes::cipher::block_padding::Pkcs7;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use md5::Md5;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::time::{Duration, Instant};
+
+use crate::credentials::Credentials;
+use crate::error::{KasaError, Result};
+use crate::http::{self, HttpResponse};
+use crate::transport::{ConnectionConfig, Transport};
+
+pub const KLAP_PORT: u16 = 80;
+const SEED_LEN: usize = 16;
+//sessions are good for a day unless the cookie says otherwise,
+//renew a little early so a request never races the expiry
+const DEFAULT_SESSION_TIMEOUT: u64 = 86400;
+const SESSION_MARGIN: u64 = 20 * 60;
+
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+//the handshake hashes changed between firmware generations
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KlapVersion {
+    V1,
+    V2,
+}
+
+impl KlapVersion {
+    fn auth_hash(&self, credentials: &Credentials) -> [u8; 32] {
+        match self {
+            KlapVersion::V1 => {
+                //v1 is md5 based, pad it out so both versions share a type
+                let user = Md5::digest(credentials.username.as_bytes());
+                let pass = Md5::digest(credentials.password.as_bytes());
+                let mut hash = [0u8; 32];
+                hash[..16]
+                    .copy_from_slice(&Md5::new().chain_update(user).chain_update(pass).finalize());
+                hash
+            }
+            KlapVersion::V2 => {
+                let user = Sha1::digest(credentials.username.as_bytes());
+                let pass = Sha1::digest(credentials.password.as_bytes());
+                sha256(&[&user, &pass])
+            }
+        }
+    }
+
+    fn auth_hash_bytes<'a>(&self, hash: &'a [u8; 32]) -> &'a [u8] {
+        match self {
+            KlapVersion::V1 => &hash[..16],
+            KlapVersion::V2 => hash,
+        }
+    }
+
+    fn server_hash(&self, local_seed: &[u8], remote_seed: &[u8], auth_hash: &[u8]) -> [u8; 32] {
+        match self {
+            KlapVersion::V1 => sha256(&[local_seed, auth_hash]),
+            KlapVersion::V2 => sha256(&[local_seed, remote_seed, auth_hash]),
+        }
+    }
+
+    fn client_hash(&self, local_seed: &[u8], remote_seed: &[u8], auth_hash: &[u8]) -> [u8; 32] {
+        match self {
+            KlapVersion::V1 => sha256(&[remote_seed, auth_hash]),
+            KlapVersion::V2 => sha256(&[remote_seed, local_seed, auth_hash]),
+        }
+    }
+}
+
+//keys derived from a completed handshake
+struct KlapSession {
+    key: [u8; 16],
+    iv: [u8; 12],
+    sig: [u8; 28],
+    seq: i32,
+    cookie: String,
+    expires: Instant,
+}
+
+impl KlapSession {
+    fn new(
+        local_seed: &[u8],
+        remote_seed: &[u8],
+        auth_hash: &[u8],
+        cookie: String,
+        timeout: Duration,
+    ) -> KlapSession {
+        let mut key = [0u8; 16];
+        key.copy_from_slice(&sha256(&[b"lsk", local_seed, remote_seed, auth_hash])[..16]);
+
+        let iv_full = sha256(&[b"iv", local_seed, remote_seed, auth_hash]);
+        let mut iv = [0u8; 12];
+        iv.copy_from_slice(&iv_full[..12]);
+        let seq = i32::from_be_bytes([iv_full[28], iv_full[29], iv_full[30], iv_full[31]]);
+
+        let mut sig = [0u8; 28];
+        sig.copy_from_slice(&sha256(&[b"ldk", local_seed, remote_seed, auth_hash])[..28]);
+
+        KlapSession {
+            key,
+            iv,
+            sig,
+            seq,
+            cookie,
+            expires: Instant::now() + timeout,
+        }
+    }
+
+    fn iv_for(&self, seq: i32) -> [u8; 16] {
+        let mut iv = [0u8; 16];
+        iv[..12].copy_from_slice(&self.iv);
+        iv[12..].copy_from_slice(&seq.to_be_bytes());
+        iv
+    }
+
+    //bumps the sequence number and returns it with the signed ciphertext
+    fn encrypt(&mut self, request: &[u8]) -> (i32, Vec<u8>) {
+        self.seq = self.seq.wrapping_add(1);
+        let ciphertext = Aes128CbcEnc::new(&self.key.into(), &self.iv_for(self.seq).into())
+            .encrypt_padded_vec_mut::<Pkcs7>(request);
+        let signature = sha256(&[&self.sig, &self.seq.to_be_bytes(), &ciphertext]);
+
+        let mut payload = signature.to_vec();
+        payload.extend_from_slice(&ciphertext);
+        (self.seq, payload)
+    }
+
+    fn decrypt(&self, seq: i32, payload: &[u8]) -> Result<Vec<u8>> {
+        if payload.len() < 32 {
+            return Err(KasaError::Crypto(format!(
+                "payload of {} bytes is shorter than its signature",
+                payload.len()
+            )));
+        }
+        let (signature, ciphertext) = payload.split_at(32);
+        if signature != sha256(&[&self.sig, &seq.to_be_bytes(), ciphertext]) {
+            return Err(KasaError::Crypto("response signature mismatch".to_string()));
+        }
+        Aes128CbcDec::new(&self.key.into(), &self.iv_for(seq).into())
+            .decrypt_padded_vec_mut::<Pkcs7>(ciphertext)
+            .map_err(|_| KasaError::Crypto("bad padding in response".to_string()))
+    }
+}
+
+pub struct KlapTransport {
+    addr: String,
+    credentials: Credentials,
+    config: ConnectionConfig,
+    session: Option<KlapSession>,
+    version: Option<KlapVersion>,
+    response: Option<String>,
+}
+
+impl KlapTransport {
+    //addr is "ip" for a real device, or "ip:port" to point at something else
+    pub fn new(addr: &str, credentials: Credentials, config: ConnectionConfig) -> KlapTransport {
+        KlapTransport {
+            addr: addr.to_string(),
+            credentials,
+            config,
+            session: None,
+            version: None,
+            response: None,
+        }
+    }
+
+    //the handshake variant the device accepted, once one has run
+    pub fn version(&self) -> Option<KlapVersion> {
+        self.version
+    }
+
+    fn post(&self, path: &str, cookie: Option<&str>, body: &[u8]) -> Result<HttpResponse> {
+        let cookie = cookie.map(|cookie| format!("TP_SESSIONID={cookie}"));
+        let mut headers = vec![("Content-Type", "application/octet-stream")];
+        if let Some(cookie) = cookie.as_deref() {
+            headers.push(("Cookie", cookie));
+        }
+        http::post(&self.addr, KLAP_PORT, path, &headers, body, &self.config)
+    }
+
+    pub fn handshake(&mut self) -> Result<()> {
+        let mut local_seed = [0u8; SEED_LEN];
+        getrandom::getrandom(&mut local_seed)
+            .map_err(|err| KasaError::Crypto(format!("no randomness for seed: {err}")))?;
+
+        let resp = self.post("/app/handshake1", None, &local_seed)?;
+        if resp.status != 200 {
+            return Err(KasaError::Http(format!(
+                "handshake1 returned {}",
+                resp.status
+            )));
+        }
+        if resp.body.len() != SEED_LEN + 32 {
+            return Err(KasaError::Http(format!(
+                "handshake1 reply is {} bytes, expected {}",
+                resp.body.len(),
+                SEED_LEN + 32
+            )));
+        }
+        let (remote_seed, server_hash) = resp.body.split_at(SEED_LEN);
+        let cookie = resp
+            .cookie("TP_SESSIONID")
+            .ok_or_else(|| KasaError::Http("handshake1 set no session cookie".to_string()))?
+            .to_string();
+        let timeout = resp
+            .cookie("TIMEOUT")
+            .and_then(|timeout| timeout.parse::<u64>().ok())
+            .unwrap_or(DEFAULT_SESSION_TIMEOUT);
+
+        //work out which credentials (and hash variant) the device was set up with,
+        //unbound devices answer to the defaults rather than the account login
+        let candidates = [
+            self.credentials.clone(),
+            Credentials::kasa_default(),
+            Credentials::blank(),
+        ];
+        let (version, auth_hash) = candidates
+            .iter()
+            .flat_map(|creds| [KlapVersion::V2, KlapVersion::V1].map(|v| (v, v.auth_hash(creds))))
+            .find(|(version, hash)| {
+                version.server_hash(&local_seed, remote_seed, version.auth_hash_bytes(hash))[..]
+                    == *server_hash
+            })
+            .ok_or_else(|| {
+                KasaError::Authentication("device did not accept the credentials".to_string())
+            })?;
+        let auth_hash = version.auth_hash_bytes(&auth_hash);
+
+        let client_hash = version.client_hash(&local_seed, remote_seed, auth_hash);
+        let resp = self.post("/app/handshake2", Some(&cookie), &client_hash)?;
+        if resp.status != 200 {
+            return Err(KasaError::Authentication(format!(
+                "handshake2 returned {}",
+                resp.status
+            )));
+        }
+
+        self.version = Some(version);
+        self.session = Some(KlapSession::new(
+            &local_seed,
+            remote_seed,
+            auth_hash,
+            cookie,
+            Duration::from_secs(timeout.saturating_sub(SESSION_MARGIN)),
+        ));
+        Ok(())
+    }
+
+    fn request(&mut self, request: &str) -> Result<HttpResponse> {
+        if !self.is_connected() {
+            self.handshake()?;
+        }
+        let session = self
+            .session
+            .as_mut()
+            .ok_or_else(|| KasaError::Authentication("no klap session".to_string()))?;
+        let (seq, payload) = session.encrypt(request.as_bytes());
+        let cookie = session.cookie.clone();
+
+        let mut resp = self.post(&format!("/app/request?seq={seq}"), Some(&cookie), &payload)?;
+        if resp.status == 200 {
+            if let Some(session) = &self.session {
+                resp.body = session.decrypt(seq, &resp.body)?;
+            }
+        }
+        Ok(resp)
+    }
+}
+
+impl Transport for KlapTransport {
+    fn send(&mut self, request: &str) -> Result<()> {
+        let mut resp = self.request(request)?;
+        //the device forgets sessions on reboot, a 403 means handshake again
+        if resp.status == 403 {
+            self.disconnect();
+            resp = self.request(request)?;
+        }
+        if resp.status != 200 {
+            self.disconnect();
+            return Err(KasaError::Http(format!("request returned {}", resp.status)));
+        }
+        self.response = Some(String::from_utf8_lossy(&resp.body).into_owned());
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        self.response
+            .take()
+            .ok_or_else(|| KasaError::MissingField("klap response".to_string()))
+    }
+
+    fn is_connected(&self) -> bool {
+        self.session
+            .as_ref()
+            .is_some_and(|session| session.expires > Instant::now())
+    }
+
+    fn disconnect(&mut self) {
+        self.session = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::thread;
+
+    const REQUEST: &str = r#"{"system":{"get_sysinfo":null}}"#;
+    const REPLY: &str = r#"{"system":{"get_sysinfo":{"alias":"stand-in","err_code":0}}}"#;
+
+    //path and body of one http request
+    fn read_request(stream: &mut BufReader<TcpStream>) -> (String, Vec<u8>) {
+        let mut line = String::new();
+        stream.read_line(&mut line).unwrap();
+        let path = line.split_whitespace().nth(1).unwrap().to_string();
+        let mut len = 0;
+        loop {
+            line.clear();
+            stream.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((key, value)) = line.split_once(':') {
+                if key.eq_ignore_ascii_case("content-length") {
+                    len = value.trim().parse().unwrap();
+                }
+            }
+        }
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).unwrap();
+        (path, body)
+    }
+
+    fn respond(stream: &mut TcpStream, extra_headers: &str, body: &[u8]) {
+        let head = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n{extra_headers}\r\n",
+            body.len()
+        );
+        stream.write_all(head.as_bytes()).unwrap();
+        stream.write_all(body).unwrap();
+    }
+
+    //plays the device side of handshake1, handshake2 and one request. the
+    //connections are left open after each reply, so a client reading until
+    //close would hang rather than pass
+    fn stand_in(
+        version: KlapVersion,
+        credentials: Credentials,
+    ) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let remote_seed = [7u8; SEED_LEN];
+            let auth_hash = version.auth_hash(&credentials);
+            let auth_hash = version.auth_hash_bytes(&auth_hash).to_vec();
+            let mut local_seed = vec![];
+            let mut open = vec![];
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (path, body) = read_request(&mut reader);
+                if path == "/app/handshake1" {
+                    local_seed = body;
+                    let mut reply = remote_seed.to_vec();
+                    reply.extend(version.server_hash(&local_seed, &remote_seed, &auth_hash));
+                    respond(
+                        &mut stream,
+                        "Set-Cookie: TP_SESSIONID=stand-in;TIMEOUT=86400\r\n",
+                        &reply,
+                    );
+                } else if path == "/app/handshake2" {
+                    assert_eq!(
+                        body,
+                        version.client_hash(&local_seed, &remote_seed, &auth_hash)
+                    );
+                    respond(&mut stream, "", &[]);
+                } else {
+                    let seq: i32 = path.split_once("seq=").unwrap().1.parse().unwrap();
+                    let mut session = KlapSession::new(
+                        &local_seed,
+                        &remote_seed,
+                        &auth_hash,
+                        String::new(),
+                        Duration::from_secs(60),
+                    );
+                    assert_eq!(session.decrypt(seq, &body).unwrap(), REQUEST.as_bytes());
+                    session.seq = seq.wrapping_sub(1);
+                    let (_, reply) = session.encrypt(REPLY.as_bytes());
+                    respond(&mut stream, "", &reply);
+                }
+                open.push(stream);
+            }
+        });
+        (addr, handle)
+    }
+
+    fn round_trip(version: KlapVersion) {
+        let credentials = Credentials::new("user@example.com", "hunter2");
+        let (addr, handle) = stand_in(version, credentials.clone());
+        let config = ConnectionConfig {
+            read_timeout: Some(Duration::from_secs(5)),
+            ..ConnectionConfig::default()
+        };
+        let mut transport = KlapTransport::new(&addr, credentials, config);
+        assert_eq!(transport.query(REQUEST).unwrap(), REPLY);
+        assert_eq!(transport.version(), Some(version));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn klap_v1_round_trip() {
+        round_trip(KlapVersion::V1);
+    }
+
+    #[test]
+    fn klap_v2_round_trip() {
+        round_trip(KlapVersion::V2);
+    }
+}
@@ -1,18 +1,54 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[cfg(feature = "smart")]
+pub mod aes_transport;
+#[cfg(feature = "async")]
+pub mod async_device;
+#[cfg(feature = "async")]
+pub mod async_kasa_protocol;
+#[cfg(feature = "clock")]
+pub mod clock;
+pub mod codec;
+#[cfg(feature = "std")]
+pub mod command;
+#[cfg(feature = "std")]
+pub mod credentials;
+#[cfg(feature = "std")]
 pub mod device;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "embedded")]
+pub mod fixed_models;
+#[cfg(feature = "klap")]
+mod http;
+#[cfg(feature = "std")]
 pub mod kasa_protocol;
+#[cfg(feature = "klap")]
+pub mod klap;
+#[cfg(feature = "alloc")]
 pub mod models;
+pub mod realtime;
+#[cfg(feature = "smart")]
+pub mod smart_models;
+#[cfg(feature = "smart")]
+pub mod smart_protocol;
+pub mod timezones;
+#[cfg(feature = "std")]
+pub mod transport;
 
-pub fn validate_ip(ip: &String) -> bool {
-    let ip: Vec<&str> = ip.split(".").collect();
+pub fn validate_ip(ip: &str) -> bool {
+    let mut octets = 0;
 
-    if ip.len() != 4 {
-        return false;
-    }
-    for octet in ip {
-        let _val: u8 = match octet.parse() {
-            Ok(value) => value,
-            Err(_error) => return false,
-        };
+    for octet in ip.split('.') {
+        if octet.parse::<u8>().is_err() {
+            return false;
+        }
+        octets += 1;
     }
-    return true;
+    octets == 4
 }
@@ -1,11 +1,17 @@
 use anyhow::{anyhow, Result};
-use clap::Parser;
-use rust_kasa::models::KasaResp;
-use rust_kasa::{device, kasa_protocol, models, validate_ip};
-use std::any::Any;
-use std::io::stdout;
-use std::net::TcpStream;
+use clap::{Parser, Subcommand};
+#[cfg(feature = "clock")]
+use rust_kasa::credentials::Credentials;
+use rust_kasa::device::{self, Device};
+use rust_kasa::error::KasaError;
+use rust_kasa::kasa_protocol;
+use rust_kasa::models::{AntiTheftRule, DeviceTime, KasaResp, ScheduleTime};
+use rust_kasa::transport::{ConnectionConfig, TcpTransport, Transport};
+#[cfg(feature = "clock")]
+use rust_kasa::{clock, timezones};
+use std::io::{self, Read, Write};
 use std::string::String;
+use std::time::{Duration, Instant};
 //mod app;
 
 //use app::App;
@@ -24,12 +30,735 @@ struct Cli {
 
     #[arg(short = 'a', long = "action", default_value_t = String::from(""))]
     action: String,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// send a json request as is and print the decrypted reply
+    Raw {
+        /// the request, e.g. '{"system":{"get_sysinfo":null}}'. read from stdin when omitted or "-"
+        request: Option<String>,
+
+        /// aim the request at one outlet of a strip
+        #[arg(short = 'c', long = "child")]
+        child_id: Option<String>,
+    },
+    /// emeter maintenance: show or set the calibration gains, or erase the stored totals
+    Emeter {
+        #[command(subcommand)]
+        action: EmeterAction,
+
+        /// act on one outlet of a strip
+        #[arg(short = 'c', long = "child")]
+        child_id: Option<String>,
+
+        /// don't ask before changing anything
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// switch outlets on and off at random over a range of days (anti-theft / away mode)
+    Away {
+        /// first day, YYYY-MM-DD
+        #[arg(long = "from")]
+        from: String,
+
+        /// last day, YYYY-MM-DD. the range covers at most 31 days
+        #[arg(long = "to")]
+        to: String,
+
+        /// start of the daily window, HH:MM
+        #[arg(long = "start")]
+        start: String,
+
+        /// end of the daily window, HH:MM
+        #[arg(long = "end")]
+        end: String,
+
+        /// outlet to include, repeat for several. the whole device when omitted
+        #[arg(short = 'c', long = "child")]
+        child_ids: Vec<String>,
+    },
+    /// print a summary of the device, including its cloud binding
+    Info,
+    /// inspect or change the device's tp-link cloud binding
+    Cloud {
+        #[command(subcommand)]
+        action: CloudAction,
+
+        /// don't ask before changing anything
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// join a device in setup mode to a wifi network, then find it again on that network
+    Provision {
+        /// network to join, picked from the device's scan when omitted
+        #[arg(long = "ssid")]
+        ssid: Option<String>,
+
+        /// network password, asked for when omitted
+        #[arg(long = "password")]
+        password: Option<String>,
+
+        /// 0 open, 1 wep, 2 wpa, 3 wpa2, 4 wpa3. taken from the scan when omitted
+        #[arg(long = "key-type")]
+        key_type: Option<u8>,
+
+        /// name to give the device before it leaves setup mode
+        #[arg(long = "alias")]
+        alias: Option<String>,
+
+        /// seconds to keep looking for the device on the new network
+        #[arg(long = "wait", default_value_t = 60)]
+        wait: u64,
+    },
+    #[command(flatten)]
+    System(SystemAction),
+    /// read or sync the device clock and timezone
+    Clock {
+        #[command(subcommand)]
+        action: ClockAction,
+    },
+}
+
+//the system module calls, flattened into the top level commands
+#[derive(Subcommand)]
+enum SystemAction {
+    /// reboot the device
+    Reboot {
+        /// seconds to wait before rebooting
+        #[arg(long = "delay", default_value_t = 1)]
+        delay: u32,
+
+        /// don't ask first
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// factory reset: wipes wifi, cloud binding, rules and names
+    Reset {
+        /// seconds to wait before resetting
+        #[arg(long = "delay", default_value_t = 1)]
+        delay: u32,
+
+        /// don't ask first
+        #[arg(short = 'y', long = "yes")]
+        yes: bool,
+    },
+    /// turn the status led on or off
+    Led {
+        #[arg(value_parser = ["on", "off"])]
+        state: String,
+    },
+    /// set the location used for sunrise/sunset schedules
+    Location {
+        #[arg(long = "lat", allow_hyphen_values = true)]
+        latitude: f64,
+
+        #[arg(long = "lon", allow_hyphen_values = true)]
+        longitude: f64,
+    },
+    /// rename the device, or one outlet of a strip
+    Alias {
+        alias: String,
+
+        /// rename this outlet instead of the device
+        #[arg(short = 'c', long = "child")]
+        child_id: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum CloudAction {
+    /// print the binding status and server
+    Show,
+    /// bind the device to a tp-link account
+    Bind {
+        #[arg(short = 'u', long = "username")]
+        username: String,
+
+        /// asked for without echo when omitted
+        #[arg(short = 'p', long = "password")]
+        password: Option<String>,
+    },
+    /// remove the tp-link account binding
+    Unbind,
+    /// point the device at a different cloud server
+    SetServer { server: String },
+    /// list firmware the cloud offers for the device
+    Firmware,
+}
+
+#[derive(Subcommand)]
+enum ClockAction {
+    /// print the device's clock and timezone
+    Show,
+    /// set the device's clock and timezone from this machine
+    #[cfg(feature = "clock")]
+    Sync {
+        /// kasa timezone index or IANA name, this machine's zone when omitted
+        #[arg(long = "tz")]
+        timezone: Option<String>,
+
+        /// every device that answers discovery instead of -t
+        #[arg(long = "all")]
+        all: bool,
+
+        /// seconds to wait for discovery replies with --all
+        #[arg(long = "wait", default_value_t = 2)]
+        wait: u64,
+
+        /// tp-link account for the klap devices --all finds, unbound ones need none
+        #[arg(short = 'u', long = "username", requires = "password")]
+        username: Option<String>,
+
+        /// password for --username
+        #[arg(short = 'p', long = "password", requires = "username")]
+        password: Option<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum EmeterAction {
+    /// print the voltage and current gains
+    Gain,
+    /// set the voltage gain
+    SetVgain { vgain: u32 },
+    /// set the current gain
+    SetIgain { igain: u32 },
+    /// erase the daily and monthly totals
+    Erase,
+}
+
+//anything that can't be undone asks first, unless --yes was given
+fn confirm(prompt: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    print!("{prompt} [y/N] ");
+    io::stdout().flush()?;
+    let mut answer = String::new();
+    io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+fn transport_for(target_addr: &str) -> Result<TcpTransport> {
+    if target_addr.is_empty() {
+        return Err(anyhow!("a target address (-t) is required"));
+    }
+    Ok(TcpTransport::new(target_addr, ConnectionConfig::default()))
+}
+
+fn raw(target_addr: &str, request: Option<String>, child_id: Option<&str>) -> Result<()> {
+    let mut transport = transport_for(target_addr)?;
+    let request = match request {
+        Some(request) if request != "-" => request,
+        _ => {
+            let mut request = String::new();
+            io::stdin().read_to_string(&mut request)?;
+            request
+        }
+    };
+    let request = serde_json::from_str(&request)?;
+    let resp = kasa_protocol::query_raw(&mut transport, request, child_id)?;
+    println!("{resp}");
+    Ok(())
+}
+
+fn emeter(
+    target_addr: &str,
+    action: EmeterAction,
+    child_id: Option<&str>,
+    yes: bool,
+) -> Result<()> {
+    let mut transport = transport_for(target_addr)?;
+    let target = match child_id {
+        Some(child_id) => format!("outlet {child_id} on {target_addr}"),
+        None => target_addr.to_string(),
+    };
+    let prompt = match action {
+        EmeterAction::Gain => None,
+        EmeterAction::SetVgain { vgain } => Some(format!("set vgain to {vgain} on {target}?")),
+        EmeterAction::SetIgain { igain } => Some(format!("set igain to {igain} on {target}?")),
+        EmeterAction::Erase => Some(format!("erase all emeter totals on {target}?")),
+    };
+    if let Some(prompt) = prompt {
+        if !confirm(&prompt, yes)? {
+            println!("aborted");
+            return Ok(());
+        }
+    }
+
+    match action {
+        EmeterAction::Gain => {
+            let gain = kasa_protocol::get_vgain_igain(&mut transport, child_id)?;
+            println!("vgain: {}, igain: {}", gain.vgain, gain.igain);
+        }
+        EmeterAction::SetVgain { vgain } => {
+            kasa_protocol::set_vgain(&mut transport, child_id, vgain)?
+        }
+        EmeterAction::SetIgain { igain } => {
+            kasa_protocol::set_igain(&mut transport, child_id, igain)?
+        }
+        EmeterAction::Erase => kasa_protocol::erase_emeter_stat(&mut transport, child_id)?,
+    }
+    Ok(())
+}
+
+fn parse_date(date: &str) -> Result<(u16, u8, u8)> {
+    let parts: Vec<&str> = date.split('-').collect();
+    let parsed = match parts.as_slice() {
+        [year, month, day] => (year.parse(), month.parse(), day.parse()),
+        _ => return Err(anyhow!("bad date {date:?}, expected YYYY-MM-DD")),
+    };
+    match parsed {
+        (Ok(year), Ok(month @ 1..=12), Ok(day))
+            if day >= 1 && day <= days_in_month(year, month) =>
+        {
+            Ok((year, month, day))
+        }
+        _ => Err(anyhow!("bad date {date:?}, expected YYYY-MM-DD")),
+    }
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    match month {
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => {
+            29
+        }
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn next_day((year, month, day): (u16, u8, u8)) -> (u16, u8, u8) {
+    if day < days_in_month(year, month) {
+        (year, month, day + 1)
+    } else if month < 12 {
+        (year, month + 1, 1)
+    } else {
+        (year + 1, 1, 1)
+    }
+}
+
+//minutes after midnight
+fn parse_time(time: &str) -> Result<u16> {
+    match time
+        .split_once(':')
+        .map(|(h, m)| (h.parse::<u16>(), m.parse::<u16>()))
+    {
+        Some((Ok(hour @ 0..=23), Ok(minute @ 0..=59))) => Ok(hour * 60 + minute),
+        _ => Err(anyhow!("bad time {time:?}, expected HH:MM")),
+    }
+}
+
+//the firmware only keeps a few dozen anti-theft rules per outlet, and a
+//mistyped year shouldn't turn into hundreds of requests
+const MAX_AWAY_DAYS: usize = 31;
+
+//one-off anti-theft rule per day per outlet, then switch the module on.
+//if anything fails the rules this run added are deleted again
+fn away(
+    target_addr: &str,
+    from: &str,
+    to: &str,
+    start: &str,
+    end: &str,
+    child_ids: &[String],
+) -> Result<()> {
+    let (from, to) = (parse_date(from)?, parse_date(to)?);
+    let (start, end) = (parse_time(start)?, parse_time(end)?);
+    if from > to {
+        return Err(anyhow!("--from is after --to"));
+    }
+    if end <= start {
+        return Err(anyhow!("--end has to be after --start"));
+    }
+    let mut dates = vec![from];
+    let mut date = from;
+    while date < to {
+        if dates.len() == MAX_AWAY_DAYS {
+            return Err(anyhow!("away covers at most {MAX_AWAY_DAYS} days"));
+        }
+        date = next_day(date);
+        dates.push(date);
+    }
+    let (start, end) = (ScheduleTime::At(start), ScheduleTime::At(end));
+
+    let targets: Vec<Option<&str>> = match child_ids {
+        [] => vec![None],
+        ids => ids.iter().map(|id| Some(id.as_str())).collect(),
+    };
+    let mut transport = transport_for(target_addr)?;
+    let mut added = vec![];
+    let result = targets.iter().try_for_each(|&child_id| {
+        for &date in &dates {
+            let name = format!("away {}-{:02}-{:02}", date.0, date.1, date.2);
+            let rule = AntiTheftRule::on_date(&name, date, start, end);
+            let id = kasa_protocol::add_anti_theft_rule(&mut transport, child_id, &rule)?;
+            println!("{}: added {name} ({id})", child_id.unwrap_or(target_addr));
+            added.push((child_id, id));
+        }
+        kasa_protocol::set_anti_theft_enabled(&mut transport, child_id, true)
+    });
+    let Err(err) = result else {
+        return Ok(());
+    };
+    //adds aren't resent once written, but a reply lost on the way back
+    //leaves the rule in flight on the device without us knowing its id
+    let err = match err {
+        KasaError::Timeout | KasaError::Truncated { .. } => {
+            format!("{err} (the rule being added may still have been saved)")
+        }
+        err => err.to_string(),
+    };
+
+    let left: Vec<String> = added
+        .iter()
+        .filter(|(child_id, id)| {
+            kasa_protocol::delete_anti_theft_rule(&mut transport, *child_id, id).is_err()
+        })
+        .map(|(child_id, id)| format!("{} {id}", child_id.unwrap_or(target_addr)))
+        .collect();
+    match left.as_slice() {
+        [] => Err(anyhow!(
+            "{err}, removed the {} rules added so far",
+            added.len()
+        )),
+        left => Err(anyhow!(
+            "{err}, these rules were added and could not be removed: {}",
+            left.join(", ")
+        )),
+    }
+}
+
+#[cfg(feature = "clock")]
+fn parse_timezone(timezone: &str) -> Result<u8> {
+    match timezone.parse::<u8>() {
+        Ok(index) if timezones::iana_name(index).is_some() => Ok(index),
+        Ok(index) => Err(anyhow!("no kasa timezone with index {index}")),
+        Err(_) => timezones::index_of(timezone)
+            .ok_or_else(|| anyhow!("{timezone} isn't in the kasa timezone table")),
+    }
+}
+
+fn print_time(time: &DeviceTime) -> String {
+    format!(
+        "{}-{:02}-{:02} {:02}:{:02}:{:02}",
+        time.year, time.month, time.mday, time.hour, time.min, time.sec
+    )
+}
+
+fn clock(target_addr: &str, action: ClockAction) -> Result<()> {
+    match action {
+        ClockAction::Show => {
+            let mut transport = transport_for(target_addr)?;
+            let time = kasa_protocol::get_time(&mut transport)?;
+            let timezone = kasa_protocol::get_timezone(&mut transport)?;
+            println!(
+                "{} ({}, index {})",
+                print_time(&time),
+                timezone.iana_name().unwrap_or("unknown zone"),
+                timezone.index
+            );
+        }
+        #[cfg(feature = "clock")]
+        ClockAction::Sync {
+            timezone,
+            all: true,
+            wait,
+            username,
+            password,
+        } => {
+            let index = timezone.as_deref().map(parse_timezone).transpose()?;
+            let credentials = username
+                .zip(password)
+                .map(|(username, password)| Credentials::new(&username, &password));
+            let wait = Duration::from_secs(wait);
+            for (ip_addr, result) in clock::sync_fleet(wait, index, credentials.as_ref())? {
+                match result {
+                    Ok(time) => println!("{ip_addr}: set to {}", print_time(&time)),
+                    Err(err) => println!("{ip_addr}: {err}"),
+                }
+            }
+        }
+        #[cfg(feature = "clock")]
+        ClockAction::Sync { timezone, .. } => {
+            let mut transport = transport_for(target_addr)?;
+            let index = timezone.as_deref().map(parse_timezone).transpose()?;
+            let (index, time) = clock::sync_clock(&mut transport, index)?;
+            println!(
+                "set to {} ({})",
+                print_time(&time),
+                timezones::iana_name(index).unwrap_or_default()
+            );
+        }
+    }
+    Ok(())
+}
+
+//a Device without sysinfo, for commands that don't need it up front
+fn device_for(target_addr: &str) -> Result<Device> {
+    if target_addr.is_empty() {
+        return Err(anyhow!("a target address (-t) is required"));
+    }
+    Ok(Device::new(
+        target_addr.to_string(),
+        KasaResp {
+            system: None,
+            emeter: None,
+            cloud: None,
+        },
+    ))
+}
+
+fn info(target_addr: &str) -> Result<()> {
+    let mut dev = device_for(target_addr)?;
+    dev.refresh()?;
+    println!("{dev}");
+    Ok(())
+}
+
+fn system(target_addr: &str, action: SystemAction) -> Result<()> {
+    let dev = device_for(target_addr)?;
+    match action {
+        SystemAction::Reboot { delay, yes } => {
+            if !confirm(&format!("reboot {target_addr}?"), yes)? {
+                println!("aborted");
+                return Ok(());
+            }
+            dev.reboot(delay)?;
+            println!("rebooting in {delay}s");
+        }
+        SystemAction::Reset { delay, yes } => {
+            let prompt = format!(
+                "factory reset {target_addr}? it will forget its wifi and go back to setup mode"
+            );
+            if !confirm(&prompt, yes)? {
+                println!("aborted");
+                return Ok(());
+            }
+            dev.factory_reset(delay)?;
+            println!("resetting in {delay}s");
+        }
+        SystemAction::Led { state } => dev.set_led_off(state == "off")?,
+        SystemAction::Location {
+            latitude,
+            longitude,
+        } => dev.set_location(latitude, longitude)?,
+        SystemAction::Alias {
+            alias,
+            child_id: Some(child_id),
+        } => dev.set_child_alias(&child_id, &alias)?,
+        SystemAction::Alias {
+            alias,
+            child_id: None,
+        } => dev.set_alias(&alias)?,
+    }
+    Ok(())
+}
+
+fn cloud(target_addr: &str, action: CloudAction, yes: bool) -> Result<()> {
+    let mut transport = transport_for(target_addr)?;
+    let prompt = match &action {
+        CloudAction::Unbind => Some(format!("unbind {target_addr} from its cloud account?")),
+        CloudAction::SetServer { server } => {
+            Some(format!("point {target_addr} at cloud server {server}?"))
+        }
+        _ => None,
+    };
+    if let Some(prompt) = prompt {
+        if !confirm(&prompt, yes)? {
+            println!("aborted");
+            return Ok(());
+        }
+    }
+
+    match action {
+        CloudAction::Show => {
+            let cloud = kasa_protocol::get_cloud_info(&mut transport)?;
+            match cloud.is_bound() {
+                true => println!("bound to {} on {}", cloud.username, cloud.server),
+                false => println!("not bound (server {})", cloud.server),
+            }
+            println!("connected: {}", cloud.is_connected());
+        }
+        CloudAction::Bind { username, password } => {
+            let password = match password {
+                Some(password) => password,
+                None => rpassword::prompt_password(format!("password for {username}: "))?,
+            };
+            kasa_protocol::bind_cloud(&mut transport, &username, &password)?
+        }
+        CloudAction::Unbind => kasa_protocol::unbind_cloud(&mut transport)?,
+        CloudAction::SetServer { server } => {
+            kasa_protocol::set_cloud_server_url(&mut transport, &server)?
+        }
+        CloudAction::Firmware => {
+            for fw in kasa_protocol::get_intl_fw_list(&mut transport)? {
+                println!(
+                    "{} {} ({}): {}",
+                    fw.fwTitle, fw.fwVer, fw.fwReleaseDate, fw.fwUrl
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+//devices in setup mode run their own access point and sit at this address
+const SETUP_ADDR: &str = "192.168.0.1";
+
+fn prompt_line(prompt: &str) -> Result<String> {
+    print!("{prompt}");
+    io::stdout().flush()?;
+    let mut line = String::new();
+    io::stdin().read_line(&mut line)?;
+    Ok(line.trim().to_string())
+}
+
+//sysinfo and 20002 discovery format macs differently, compare just the digits
+fn same_mac(a: &str, b: &str) -> bool {
+    let digits = |mac: &str| -> String {
+        mac.chars()
+            .filter(char::is_ascii_hexdigit)
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    };
+    digits(a) == digits(b)
+}
+
+fn provision(
+    target_addr: &str,
+    ssid: Option<String>,
+    password: Option<String>,
+    key_type: Option<u8>,
+    alias: Option<String>,
+    wait: u64,
+) -> Result<()> {
+    let addr = match target_addr {
+        "" => SETUP_ADDR,
+        addr => addr,
+    };
+    //scans take a few seconds to come back, and retrying a scan that slow
+    //would leave the user staring at nothing for most of a minute
+    let config = ConnectionConfig {
+        read_timeout: Some(Duration::from_secs(15)),
+        retries: 0,
+        ..ConnectionConfig::default()
+    };
+    let mut transport = TcpTransport::new(addr, config);
+
+    let sysinfo = kasa_protocol::get_sys_info(&mut transport)?;
+    println!("found {} ({}) at {addr}", sysinfo.model, sysinfo.mac);
+
+    let scan = kasa_protocol::get_scaninfo(&mut transport, true)?;
+    let ssid = match ssid {
+        Some(ssid) => ssid,
+        None => {
+            for (idx, ap) in scan.ap_list.iter().enumerate() {
+                println!("  [{idx}] {}", ap.ssid);
+            }
+            let choice = prompt_line("network: ")?;
+            match choice
+                .parse::<usize>()
+                .ok()
+                .and_then(|idx| scan.ap_list.get(idx))
+            {
+                Some(ap) => ap.ssid.clone(),
+                None => choice,
+            }
+        }
+    };
+    let key_type = match (key_type, scan.ap_list.iter().find(|ap| ap.ssid == ssid)) {
+        (Some(key_type), _) => key_type,
+        (None, Some(ap)) => ap.key_type,
+        (None, None) => {
+            return Err(anyhow!(
+                "the device can't see {ssid:?}, pass --key-type to join it anyway"
+            ))
+        }
+    };
+    let password = match (password, key_type) {
+        (Some(password), _) => password,
+        (None, 0) => String::new(),
+        (None, _) => rpassword::prompt_password(format!("password for {ssid}: "))?,
+    };
+
+    //has to happen now, the device is unreachable from here once it joins
+    if let Some(alias) = &alias {
+        kasa_protocol::set_dev_alias(&mut transport, alias)?;
+        println!("alias set to {alias}");
+    }
+
+    //the connection may have sat idle through the prompts. on a fresh one a
+    //reply that never comes means the device got the request, rather than
+    //the device having closed the socket before it was written
+    transport.disconnect();
+    match kasa_protocol::set_stainfo(&mut transport, &ssid, &password, key_type) {
+        Ok(()) => {}
+        //it drops off the setup ap without always answering first. a refused
+        //connection or failed write means it never got the request though
+        Err(KasaError::Timeout | KasaError::Truncated { .. }) => {}
+        Err(err) => return Err(err.into()),
+    }
+    println!("{} is joining {ssid}", sysinfo.model);
+    prompt_line(&format!(
+        "reconnect this machine to {ssid} and press enter to look for the device "
+    ))?;
+
+    let deadline = Instant::now() + Duration::from_secs(wait);
+    while Instant::now() < deadline {
+        let found = device::discover_all(Duration::from_secs(3))?
+            .into_iter()
+            .find(|dev| dev.mac().is_some_and(|mac| same_mac(mac, &sysinfo.mac)));
+        if let Some(dev) = found {
+            println!("{} is now at {}", sysinfo.model, dev.ip_addr);
+            return Ok(());
+        }
+    }
+    Err(anyhow!(
+        "{} didn't show up on {ssid}, check the password and that this machine is on the same network",
+        sysinfo.mac
+    ))
 }
 
 fn main() -> Result<()> {
     let args = Cli::parse();
 
-    if args.target_name != "" {
+    match args.command {
+        Some(Command::Raw { request, child_id }) => {
+            return raw(&args.target_addr, request, child_id.as_deref())
+        }
+        Some(Command::Emeter {
+            action,
+            child_id,
+            yes,
+        }) => return emeter(&args.target_addr, action, child_id.as_deref(), yes),
+        Some(Command::Away {
+            from,
+            to,
+            start,
+            end,
+            child_ids,
+        }) => return away(&args.target_addr, &from, &to, &start, &end, &child_ids),
+        Some(Command::Clock { action }) => return clock(&args.target_addr, action),
+        Some(Command::Info) => return info(&args.target_addr),
+        Some(Command::Cloud { action, yes }) => return cloud(&args.target_addr, action, yes),
+        Some(Command::Provision {
+            ssid,
+            password,
+            key_type,
+            alias,
+            wait,
+        }) => return provision(&args.target_addr, ssid, password, key_type, alias, wait),
+        Some(Command::System(action)) => return system(&args.target_addr, action),
+        None => {}
+    }
+
+    if !args.target_name.is_empty() {
         println!("does this work");
     }
 
@@ -90,11 +819,11 @@ fn main() -> Result<()> {
     //        print!("info {:}\n", dev.sysinfo().unwrap());
     //    }
     //}
-   // let terminal = ratatui::init();
-   // execute!(stdout(), EnterAlternateScreen).expect("failed to enter alternate screen");
-   // let app_result = App::default().run(terminal);
-   // execute!(stdout(), LeaveAlternateScreen).expect("failed to leave alternate screen");
-   // ratatui::restore();
-   // app_result
+    // let terminal = ratatui::init();
+    // execute!(stdout(), EnterAlternateScreen).expect("failed to enter alternate screen");
+    // let app_result = App::default().run(terminal);
+    // execute!(stdout(), LeaveAlternateScreen).expect("failed to leave alternate screen");
+    // ratatui::restore();
+    // app_result
     Ok(())
 }
@@ -1,12 +1,114 @@
-use std::u32;
-
+use alloc::string::String;
+use alloc::vec::Vec;
 use serde::{Deserialize, Serialize};
 
+use crate::realtime::milli;
+pub use crate::realtime::{MissingReading, Realtime};
+
 #[derive(Serialize, Deserialize, Clone)]
-pub struct NextAction {
-    pub r#type: i32,
+struct RawNextAction {
+    r#type: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    action: Option<i8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schd_sec: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    remain: Option<u32>,
 }
-#[derive(Serialize, Deserialize, Clone)]
+
+//what an outlet will do next on its own. the type numbers vary between
+//firmware, so which variant it is goes by the fields that are present
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawNextAction", into = "RawNextAction")]
+pub enum NextAction {
+    //type -1, nothing pending
+    None,
+    //a schedule rule, firing at schd_sec seconds after midnight
+    Schedule {
+        id: Option<String>,
+        action: ScheduleAction,
+        schd_sec: u32,
+    },
+    //a count_down rule, firing in remain seconds
+    Countdown {
+        id: Option<String>,
+        action: ScheduleAction,
+        remain: u32,
+    },
+    //anything else, kept by type so it survives a round trip
+    Other(i32),
+}
+
+impl From<RawNextAction> for NextAction {
+    fn from(raw: RawNextAction) -> NextAction {
+        let action = raw.action.unwrap_or(1).into();
+        match raw {
+            RawNextAction {
+                remain: Some(remain),
+                id,
+                ..
+            } => NextAction::Countdown { id, action, remain },
+            RawNextAction {
+                schd_sec: Some(schd_sec),
+                id,
+                ..
+            } => NextAction::Schedule {
+                id,
+                action,
+                schd_sec,
+            },
+            RawNextAction { r#type: -1, .. } => NextAction::None,
+            RawNextAction { r#type, .. } => NextAction::Other(r#type),
+        }
+    }
+}
+
+impl From<NextAction> for RawNextAction {
+    fn from(next: NextAction) -> RawNextAction {
+        let raw = RawNextAction {
+            r#type: -1,
+            id: None,
+            action: None,
+            schd_sec: None,
+            remain: None,
+        };
+        match next {
+            NextAction::None => raw,
+            NextAction::Schedule {
+                id,
+                action,
+                schd_sec,
+            } => RawNextAction {
+                r#type: 1,
+                id,
+                action: Some(action.into()),
+                schd_sec: Some(schd_sec),
+                ..raw
+            },
+            NextAction::Countdown { id, action, remain } => RawNextAction {
+                r#type: 2,
+                id,
+                action: Some(action.into()),
+                remain: Some(remain),
+                ..raw
+            },
+            NextAction::Other(r#type) => RawNextAction { r#type, ..raw },
+        }
+    }
+}
+
+impl NextAction {
+    //seconds until a pending countdown fires
+    pub fn remaining(&self) -> Option<u32> {
+        match self {
+            NextAction::Countdown { remain, .. } => Some(*remain),
+            _ => None,
+        }
+    }
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct KasaChildren {
     pub id: String,
     pub state: u8,
@@ -15,7 +117,7 @@ pub struct KasaChildren {
     pub next_action: NextAction,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(non_snake_case)] //kasa json mixes snake and camel and I don't have control of that
 pub struct SysInfo {
     pub alias: String,
@@ -45,28 +147,645 @@ pub struct SysInfo {
     pub updating: u32,
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
-pub struct Realtime {
-    pub current_ma: u32,
-    pub err_code: u32,
-    pub power_mw: u32,
-    pub slot_id: u32,
-    pub total_wh: u32,
-    pub voltage_mv: u32,
+//firmware before ~1.0.8 reports cumulative energy as `energy` in kWh,
+//later versions as `energy_wh` in Wh. both stat types normalise to Wh
+#[derive(Deserialize)]
+struct RawEnergy {
+    #[serde(default)]
+    energy_wh: Option<u32>,
+    #[serde(default)]
+    energy: Option<f32>,
+}
+
+impl RawEnergy {
+    fn wh(&self) -> u32 {
+        match (self.energy_wh, self.energy) {
+            (Some(wh), _) => wh,
+            (None, Some(kwh)) => milli(kwh),
+            (None, None) => 0,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawDayStat {
+    year: u16,
+    month: u8,
+    day: u8,
+    #[serde(flatten)]
+    energy: RawEnergy,
+}
+
+//one day of emeter.get_daystat
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "RawDayStat")]
+pub struct DayStat {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub energy_wh: u32,
+}
+
+impl From<RawDayStat> for DayStat {
+    fn from(raw: RawDayStat) -> DayStat {
+        DayStat {
+            year: raw.year,
+            month: raw.month,
+            day: raw.day,
+            energy_wh: raw.energy.wh(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RawMonthStat {
+    year: u16,
+    month: u8,
+    #[serde(flatten)]
+    energy: RawEnergy,
+}
+
+//one month of emeter.get_monthstat
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(from = "RawMonthStat")]
+pub struct MonthStat {
+    pub year: u16,
+    pub month: u8,
+    pub energy_wh: u32,
+}
+
+impl From<RawMonthStat> for MonthStat {
+    fn from(raw: RawMonthStat) -> MonthStat {
+        MonthStat {
+            year: raw.year,
+            month: raw.month,
+            energy_wh: raw.energy.wh(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DayStatList {
+    #[serde(default)]
+    pub day_list: Vec<DayStat>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MonthStatList {
+    #[serde(default)]
+    pub month_list: Vec<MonthStat>,
+}
+
+//emeter calibration, raw multipliers the meter applies to its voltage and
+//current readings
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Gain {
+    pub vgain: u32,
+    pub igain: u32,
+}
+
+//when a schedule rule fires. the sun based ones follow the device's
+//location (set_dev_location) and are offset by that many minutes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleTime {
+    //minutes after midnight, device local time
+    At(u16),
+    Sunrise(i16),
+    Sunset(i16),
+}
+
+//what a schedule, countdown or anti-theft rule does to the relay
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScheduleAction {
+    Off,
+    On,
+}
+
+impl From<i8> for ScheduleAction {
+    fn from(act: i8) -> ScheduleAction {
+        match act {
+            0 => ScheduleAction::Off,
+            _ => ScheduleAction::On,
+        }
+    }
+}
+
+impl From<ScheduleAction> for i8 {
+    fn from(action: ScheduleAction) -> i8 {
+        match action {
+            ScheduleAction::Off => 0,
+            ScheduleAction::On => 1,
+        }
+    }
+}
+
+//the rule as the device sends it, ints for flags and the time split over
+//stime_opt/smin/soffset. end actions (eact/etime_opt) are only used by bulbs
+#[derive(Serialize, Deserialize, Clone)]
+struct RawScheduleRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    enable: u8,
+    wday: [u8; 7],
+    stime_opt: i8,
+    #[serde(default)]
+    smin: u16,
+    #[serde(default)]
+    soffset: i16,
+    sact: i8,
+    #[serde(default = "disabled")]
+    eact: i8,
+    #[serde(default = "disabled")]
+    etime_opt: i8,
+    #[serde(default)]
+    emin: u16,
+    repeat: u8,
+    #[serde(default)]
+    year: u16,
+    #[serde(default)]
+    month: u8,
+    #[serde(default)]
+    day: u8,
+}
+
+fn disabled() -> i8 {
+    -1
+}
+
+//an entry of schedule.get_rules. id is None for a rule that hasn't been
+//added yet, the device assigns one. one-off rules (repeat false) fire on
+//year/month/day, repeating ones on the days set in wday
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawScheduleRule", into = "RawScheduleRule")]
+pub struct ScheduleRule {
+    pub id: Option<String>,
+    pub name: String,
+    pub enable: bool,
+    //sunday first
+    pub wday: [bool; 7],
+    pub start: ScheduleTime,
+    pub action: ScheduleAction,
+    pub repeat: bool,
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl ScheduleRule {
+    //a repeating rule, enabled, on the given days
+    pub fn new(name: &str, wday: [bool; 7], start: ScheduleTime, action: ScheduleAction) -> Self {
+        ScheduleRule {
+            id: None,
+            name: name.into(),
+            enable: true,
+            wday,
+            start,
+            action,
+            repeat: true,
+            year: 0,
+            month: 0,
+            day: 0,
+        }
+    }
+}
+
+impl From<RawScheduleRule> for ScheduleRule {
+    fn from(raw: RawScheduleRule) -> ScheduleRule {
+        ScheduleRule {
+            id: raw.id,
+            name: raw.name,
+            enable: raw.enable != 0,
+            wday: raw.wday.map(|day| day != 0),
+            start: schedule_time(raw.stime_opt, raw.smin, raw.soffset),
+            action: raw.sact.into(),
+            repeat: raw.repeat != 0,
+            year: raw.year,
+            month: raw.month,
+            day: raw.day,
+        }
+    }
+}
+
+impl From<ScheduleRule> for RawScheduleRule {
+    fn from(rule: ScheduleRule) -> RawScheduleRule {
+        let (stime_opt, smin, soffset) = time_fields(rule.start);
+        RawScheduleRule {
+            id: rule.id,
+            name: rule.name,
+            enable: rule.enable.into(),
+            wday: rule.wday.map(u8::from),
+            stime_opt,
+            smin,
+            soffset,
+            sact: rule.action.into(),
+            eact: disabled(),
+            etime_opt: disabled(),
+            emin: 0,
+            repeat: rule.repeat.into(),
+            year: rule.year,
+            month: rule.month,
+            day: rule.day,
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone)]
+struct RawAntiTheftRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    enable: u8,
+    wday: [u8; 7],
+    stime_opt: i8,
+    #[serde(default)]
+    smin: u16,
+    #[serde(default)]
+    soffset: i16,
+    etime_opt: i8,
+    #[serde(default)]
+    emin: u16,
+    #[serde(default)]
+    eoffset: i16,
+    #[serde(default)]
+    frequency: u8,
+    repeat: u8,
+    #[serde(default)]
+    year: u16,
+    #[serde(default)]
+    month: u8,
+    #[serde(default)]
+    day: u8,
+}
+
+//split a ScheduleTime back into the opt/min/offset triple the rules use
+fn time_fields(time: ScheduleTime) -> (i8, u16, i16) {
+    match time {
+        ScheduleTime::At(minute) => (0, minute, 0),
+        ScheduleTime::Sunrise(offset) => (1, 0, offset),
+        ScheduleTime::Sunset(offset) => (2, 0, offset),
+    }
+}
+
+fn schedule_time(opt: i8, min: u16, offset: i16) -> ScheduleTime {
+    match opt {
+        1 => ScheduleTime::Sunrise(offset),
+        2 => ScheduleTime::Sunset(offset),
+        _ => ScheduleTime::At(min),
+    }
+}
+
+//an anti_theft ("away mode") rule. between start and end the outlet is
+//switched on and off at random, roughly frequency times. like schedule rules,
+//one-offs (repeat false) run on year/month/day, repeating ones on wday
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawAntiTheftRule", into = "RawAntiTheftRule")]
+pub struct AntiTheftRule {
+    pub id: Option<String>,
+    pub name: String,
+    pub enable: bool,
+    //sunday first
+    pub wday: [bool; 7],
+    pub start: ScheduleTime,
+    pub end: ScheduleTime,
+    pub frequency: u8,
+    pub repeat: bool,
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+}
+
+impl AntiTheftRule {
+    //a one-off rule for a single day, with the app's default frequency
+    pub fn on_date(
+        name: &str,
+        (year, month, day): (u16, u8, u8),
+        start: ScheduleTime,
+        end: ScheduleTime,
+    ) -> AntiTheftRule {
+        let mut wday = [false; 7];
+        wday[weekday(year, month, day)] = true;
+        AntiTheftRule {
+            id: None,
+            name: name.into(),
+            enable: true,
+            wday,
+            start,
+            end,
+            frequency: 5,
+            repeat: false,
+            year,
+            month,
+            day,
+        }
+    }
+}
+
+//day of the week, sunday = 0, for the gregorian calendar (sakamoto's method)
+fn weekday(year: u16, month: u8, day: u8) -> usize {
+    const OFFSETS: [usize; 12] = [0, 3, 2, 5, 0, 3, 5, 1, 4, 6, 2, 4];
+    let month = month.clamp(1, 12) as usize;
+    let year = (year as usize).saturating_sub(usize::from(month < 3));
+    (year + year / 4 - year / 100 + year / 400 + OFFSETS[month - 1] + day as usize) % 7
+}
+
+impl From<RawAntiTheftRule> for AntiTheftRule {
+    fn from(raw: RawAntiTheftRule) -> AntiTheftRule {
+        AntiTheftRule {
+            id: raw.id,
+            name: raw.name,
+            enable: raw.enable != 0,
+            wday: raw.wday.map(|day| day != 0),
+            start: schedule_time(raw.stime_opt, raw.smin, raw.soffset),
+            end: schedule_time(raw.etime_opt, raw.emin, raw.eoffset),
+            frequency: raw.frequency,
+            repeat: raw.repeat != 0,
+            year: raw.year,
+            month: raw.month,
+            day: raw.day,
+        }
+    }
+}
+
+impl From<AntiTheftRule> for RawAntiTheftRule {
+    fn from(rule: AntiTheftRule) -> RawAntiTheftRule {
+        let (stime_opt, smin, soffset) = time_fields(rule.start);
+        let (etime_opt, emin, eoffset) = time_fields(rule.end);
+        RawAntiTheftRule {
+            id: rule.id,
+            name: rule.name,
+            enable: rule.enable.into(),
+            wday: rule.wday.map(u8::from),
+            stime_opt,
+            smin,
+            soffset,
+            etime_opt,
+            emin,
+            eoffset,
+            frequency: rule.frequency,
+            repeat: rule.repeat.into(),
+            year: rule.year,
+            month: rule.month,
+            day: rule.day,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AntiTheftRuleList {
+    #[serde(default)]
+    pub rule_list: Vec<AntiTheftRule>,
+    #[serde(default)]
+    pub enable: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct RawCountdownRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(default)]
+    name: String,
+    enable: u8,
+    delay: u32,
+    act: i8,
+    #[serde(default, skip_serializing)]
+    remain: u32,
+}
+
+//a count_down rule, "turn off in 2 hours". delay is in seconds and remain is
+//what's left of it while the rule is running, only ever read from the device
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(from = "RawCountdownRule", into = "RawCountdownRule")]
+pub struct CountdownRule {
+    pub id: Option<String>,
+    pub name: String,
+    pub enable: bool,
+    pub delay: u32,
+    pub action: ScheduleAction,
+    pub remain: u32,
+}
+
+impl CountdownRule {
+    pub fn new(name: &str, delay: u32, action: ScheduleAction) -> CountdownRule {
+        CountdownRule {
+            id: None,
+            name: name.into(),
+            enable: true,
+            delay,
+            action,
+            remain: 0,
+        }
+    }
+}
+
+impl From<RawCountdownRule> for CountdownRule {
+    fn from(raw: RawCountdownRule) -> CountdownRule {
+        CountdownRule {
+            id: raw.id,
+            name: raw.name,
+            enable: raw.enable != 0,
+            delay: raw.delay,
+            action: raw.act.into(),
+            remain: raw.remain,
+        }
+    }
+}
+
+impl From<CountdownRule> for RawCountdownRule {
+    fn from(rule: CountdownRule) -> RawCountdownRule {
+        RawCountdownRule {
+            id: rule.id,
+            name: rule.name,
+            enable: rule.enable.into(),
+            delay: rule.delay,
+            act: rule.action.into(),
+            remain: rule.remain,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CountdownRuleList {
+    #[serde(default)]
+    pub rule_list: Vec<CountdownRule>,
+}
+
+//reply to get_rules. enable is the module wide switch from set_overall_enable
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScheduleRuleList {
+    #[serde(default)]
+    pub rule_list: Vec<ScheduleRule>,
+    #[serde(default)]
+    pub enable: u8,
+}
+
+//the device clock from time.get_time, in the device's local time
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceTime {
+    pub year: u16,
+    pub month: u8,
+    pub mday: u8,
+    pub hour: u8,
+    pub min: u8,
+    pub sec: u8,
+}
+
+//time.get_timezone. index is into timezones::TIMEZONES, the rest is only
+//sent by newer firmware
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Timezone {
+    pub index: u8,
+    #[serde(default)]
+    pub zone_str: Option<String>,
+    #[serde(default)]
+    pub tz_str: Option<String>,
+    #[serde(default)]
+    pub dst_offset: Option<i32>,
+}
+
+impl Timezone {
+    pub fn iana_name(&self) -> Option<&'static str> {
+        crate::timezones::iana_name(self.index)
+    }
+}
+
+//cnCloud.get_info, the device's tp-link cloud binding
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)] //same story as SysInfo
+pub struct CloudInfo {
+    #[serde(default)]
+    pub username: String,
+    #[serde(default)]
+    pub server: String,
+    #[serde(default)]
+    pub binded: u8,
+    #[serde(default)]
+    pub cld_connection: u8,
+    #[serde(default)]
+    pub illegalType: i32,
+    #[serde(default)]
+    pub stopConnect: u8,
+    #[serde(default)]
+    pub tcspStatus: i32,
+    #[serde(default)]
+    pub fwDlPage: String,
+    #[serde(default)]
+    pub fwNotifyType: i32,
+}
+
+impl CloudInfo {
+    //tied to a tp-link account
+    pub fn is_bound(&self) -> bool {
+        self.binded != 0
+    }
+
+    //currently holding a connection to the cloud server
+    pub fn is_connected(&self) -> bool {
+        self.cld_connection != 0
+    }
+}
+
+//an entry of cnCloud.get_intl_fw_list, firmware the cloud offers the device
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[allow(non_snake_case)]
+pub struct FirmwareEntry {
+    #[serde(default)]
+    pub fwType: i32,
+    #[serde(default)]
+    pub fwTitle: String,
+    #[serde(default)]
+    pub fwVer: String,
+    #[serde(default)]
+    pub fwUrl: String,
+    #[serde(default)]
+    pub fwReleaseDate: String,
+    #[serde(default)]
+    pub fwReleaseLog: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FirmwareList {
+    #[serde(default)]
+    pub fw_list: Vec<FirmwareEntry>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CnCloud {
+    pub get_info: Option<CloudInfo>,
+}
+
+//an access point from netif.get_scaninfo. key_type is what set_stainfo
+//wants back: 0 open, 1 wep, 2 wpa, 3 wpa2, 4 wpa3 on firmware that has it
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AccessPoint {
+    pub ssid: String,
+    pub key_type: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScanInfo {
+    #[serde(default)]
+    pub ap_list: Vec<AccessPoint>,
+    #[serde(default)]
+    pub wpa3_support: u8,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct System {
     pub get_sysinfo: Option<SysInfo>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Emeter {
     pub get_realtime: Option<Realtime>,
+    pub get_daystat: Option<DayStatList>,
+    pub get_monthstat: Option<MonthStatList>,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct KasaResp {
     pub system: Option<System>,
     pub emeter: Option<Emeter>,
+    #[serde(default, rename = "cnCloud")]
+    pub cloud: Option<CnCloud>,
+}
+
+//reply to the port 20002 discovery query, sent by devices that
+//speak KLAP or the AES passthrough instead of the xor protocol
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct EncryptionScheme {
+    #[serde(default)]
+    pub is_support_https: bool,
+    pub encrypt_type: String,
+    #[serde(default)]
+    pub http_port: Option<u16>,
+    //klap login version, 2 on everything recent
+    #[serde(default)]
+    pub lv: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiscoveryResult {
+    pub device_id: String,
+    pub device_type: String,
+    pub device_model: String,
+    pub ip: String,
+    pub mac: String,
+    #[serde(default)]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub hw_ver: Option<String>,
+    #[serde(default)]
+    pub is_support_iot_cloud: Option<bool>,
+    #[serde(default)]
+    pub factory_default: Option<bool>,
+    pub mgt_encrypt_schm: EncryptionScheme,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DiscoveryResp {
+    pub result: DiscoveryResult,
+    pub error_code: i32,
 }
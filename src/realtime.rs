@@ -0,0 +1,143 @@
+//emeter realtime readings, shared by models and fixed_models. nothing here
+//allocates, so the same type serves both the std and the embedded builds
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+//fractional units to the integer milli units, no f32::round without std and
+//none of the readings are negative
+pub(crate) fn milli(value: f32) -> u32 {
+    (value * 1000.0 + 0.5) as u32
+}
+
+//emeter.get_realtime comes in two shapes. current firmware sends integer
+//milli units, HS110 v1 and other early firmware sends floats in A/W/V/kWh
+//and has no slot_id. either way it ends up as a Realtime in milli units
+#[derive(Deserialize)]
+struct RawRealtime {
+    #[serde(default)]
+    current_ma: Option<u32>,
+    #[serde(default)]
+    power_mw: Option<u32>,
+    #[serde(default)]
+    voltage_mv: Option<u32>,
+    #[serde(default)]
+    total_wh: Option<u32>,
+    #[serde(default)]
+    current: Option<f32>,
+    #[serde(default)]
+    power: Option<f32>,
+    #[serde(default)]
+    voltage: Option<f32>,
+    #[serde(default)]
+    total: Option<f32>,
+    #[serde(default)]
+    slot_id: u32,
+    #[serde(default)]
+    err_code: i32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+#[serde(try_from = "RawRealtime")]
+pub struct Realtime {
+    pub current_ma: u32,
+    pub err_code: i32,
+    pub power_mw: u32,
+    pub slot_id: u32,
+    pub total_wh: u32,
+    pub voltage_mv: u32,
+}
+
+impl TryFrom<RawRealtime> for Realtime {
+    type Error = MissingReading;
+
+    fn try_from(raw: RawRealtime) -> Result<Realtime, MissingReading> {
+        Ok(Realtime {
+            current_ma: reading(raw.current_ma, raw.current, "current_ma")?,
+            err_code: raw.err_code,
+            power_mw: reading(raw.power_mw, raw.power, "power_mw")?,
+            slot_id: raw.slot_id,
+            total_wh: reading(raw.total_wh, raw.total, "total_wh")?,
+            voltage_mv: reading(raw.voltage_mv, raw.voltage, "voltage_mv")?,
+        })
+    }
+}
+
+//a realtime reply with neither form of one of the readings. zero is a real
+//reading, so it isn't a stand in for one that's missing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MissingReading(pub &'static str);
+
+impl fmt::Display for MissingReading {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "realtime reply is missing {}", self.0)
+    }
+}
+
+//the milli unit field when present, else the fractional one converted
+fn reading(
+    milli_units: Option<u32>,
+    units: Option<f32>,
+    field: &'static str,
+) -> Result<u32, MissingReading> {
+    milli_units
+        .or(units.map(milli))
+        .ok_or(MissingReading(field))
+}
+
+impl Realtime {
+    pub fn watts(&self) -> f32 {
+        self.power_mw as f32 / 1000.0
+    }
+
+    pub fn volts(&self) -> f32 {
+        self.voltage_mv as f32 / 1000.0
+    }
+
+    pub fn amps(&self) -> f32 {
+        self.current_ma as f32 / 1000.0
+    }
+
+    pub fn kwh(&self) -> f32 {
+        self.total_wh as f32 / 1000.0
+    }
+}
+
+#[cfg(all(test, any(feature = "alloc", feature = "embedded")))]
+mod tests {
+    use super::*;
+    #[cfg(feature = "alloc")]
+    use alloc::string::ToString;
+
+    const HS110_V1: &str = r#"{"current":0.012933,"voltage":227.832165,"power":0.917597,"total":0.001000,"err_code":0}"#;
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn realtime_from_hs110_v1_floats() {
+        let rt: Realtime = serde_json::from_str(HS110_V1).unwrap();
+        assert_eq!(rt.current_ma, 13);
+        assert_eq!(rt.voltage_mv, 227832);
+        assert_eq!(rt.power_mw, 918);
+        assert_eq!(rt.total_wh, 1);
+        assert_eq!(rt.slot_id, 0);
+    }
+
+    #[cfg(feature = "alloc")]
+    #[test]
+    fn realtime_missing_reading_is_an_error() {
+        let err = serde_json::from_str::<Realtime>(r#"{"foo":1}"#).unwrap_err();
+        assert!(err.to_string().contains("missing current_ma"), "{err}");
+
+        let json = r#"{"current_ma":22,"power_mw":1041,"total_wh":17,"err_code":0}"#;
+        let err = serde_json::from_str::<Realtime>(json).unwrap_err();
+        assert!(err.to_string().contains("missing voltage_mv"), "{err}");
+    }
+
+    #[cfg(feature = "embedded")]
+    #[test]
+    fn fixed_realtime_from_hs110_v1_floats() {
+        let (rt, _) = serde_json_core::from_str::<Realtime>(HS110_V1).unwrap();
+        assert_eq!(rt.current_ma, 13);
+        assert_eq!(rt.voltage_mv, 227832);
+        assert!(serde_json_core::from_str::<Realtime>(r#"{"foo":1}"#).is_err());
+    }
+}
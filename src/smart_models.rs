@@ -0,0 +1,72 @@
+//response models for the tapo "SMART" json-rpc protocol, see smart_protocol
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+
+//nickname and ssid come back base64 encoded
+fn decode_b64(value: &str) -> String {
+    STANDARD
+        .decode(value)
+        .map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SmartDeviceInfo {
+    pub device_id: String,
+    #[serde(default)]
+    pub model: String,
+    #[serde(default)]
+    pub r#type: String,
+    #[serde(default)]
+    pub fw_ver: String,
+    #[serde(default)]
+    pub hw_ver: String,
+    #[serde(default)]
+    pub mac: String,
+    #[serde(default)]
+    pub nickname: String,
+    #[serde(default)]
+    pub device_on: bool,
+    #[serde(default)]
+    pub on_time: u64,
+    #[serde(default)]
+    pub rssi: Option<i32>,
+    #[serde(default)]
+    pub ssid: Option<String>,
+    //only set on the children of a strip
+    #[serde(default)]
+    pub position: Option<u32>,
+    #[serde(default)]
+    pub slot_number: Option<u32>,
+}
+
+impl SmartDeviceInfo {
+    pub fn alias(&self) -> String {
+        decode_b64(&self.nickname)
+    }
+
+    pub fn ssid(&self) -> Option<String> {
+        self.ssid.as_deref().map(decode_b64)
+    }
+}
+
+//get_energy_usage, energy is in Wh, runtime in minutes and power in mW
+#[derive(Serialize, Deserialize, Debug, Clone, Copy)]
+pub struct EnergyUsage {
+    pub today_runtime: u32,
+    pub month_runtime: u32,
+    pub today_energy: u32,
+    pub month_energy: u32,
+    #[serde(default)]
+    pub current_power: Option<u32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChildDeviceList {
+    pub child_device_list: Vec<SmartDeviceInfo>,
+    #[serde(default)]
+    pub start_index: usize,
+    #[serde(default)]
+    pub sum: usize,
+}
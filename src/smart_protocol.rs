@@ -0,0 +1,139 @@
+//commands for tapo plugs and strips (P110, P300, ...), which use a json-rpc
+//style {"method": ..., "params": ...} request instead of the kasa module nesting.
+//these ride on any Transport, in practice klap::KlapTransport or
+//aes_transport::AesTransport
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{KasaError, Result};
+use crate::kasa_protocol::child_at;
+use crate::models::{KasaChildren, NextAction};
+use crate::smart_models::{ChildDeviceList, EnergyUsage, SmartDeviceInfo};
+use crate::transport::Transport;
+
+pub fn smart_request(method: &str, params: Option<Value>) -> Value {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default();
+    let mut request = json!({
+        "method": method,
+        "request_time_milis": millis,
+    });
+    if let Some(params) = params {
+        request["params"] = params;
+    }
+    request
+}
+
+//unwraps {"error_code": 0, "result": {...}}, turning a nonzero code into an error
+pub fn check_smart_resp(resp: Value) -> Result<Value> {
+    match resp.get("error_code").and_then(Value::as_i64) {
+        Some(0) => Ok(resp.get("result").cloned().unwrap_or(Value::Null)),
+        Some(err_code) => Err(KasaError::Device {
+            err_code,
+            err_msg: resp.get("msg").and_then(Value::as_str).map(String::from),
+        }),
+        None => Err(KasaError::MissingField("error_code".to_string())),
+    }
+}
+
+pub fn query_smart<T: Transport + ?Sized>(
+    transport: &mut T,
+    method: &str,
+    params: Option<Value>,
+) -> Result<Value> {
+    let request = smart_request(method, params).to_string();
+    let resp: Value = serde_json::from_str(&transport.query(&request)?)?;
+    check_smart_resp(resp)
+}
+
+fn query_smart_as<T: Transport + ?Sized, R: DeserializeOwned>(
+    transport: &mut T,
+    method: &str,
+    params: Option<Value>,
+) -> Result<R> {
+    Ok(R::deserialize(query_smart(transport, method, params)?)?)
+}
+
+pub fn get_device_info<T: Transport + ?Sized>(transport: &mut T) -> Result<SmartDeviceInfo> {
+    query_smart_as(transport, "get_device_info", None)
+}
+
+pub fn get_energy_usage<T: Transport + ?Sized>(transport: &mut T) -> Result<EnergyUsage> {
+    query_smart_as(transport, "get_energy_usage", None)
+}
+
+pub fn get_child_device_list<T: Transport + ?Sized>(transport: &mut T) -> Result<ChildDeviceList> {
+    query_smart_as(transport, "get_child_device_list", None)
+}
+
+pub fn set_device_on<T: Transport + ?Sized>(transport: &mut T, on: bool) -> Result<()> {
+    query_smart(
+        transport,
+        "set_device_info",
+        Some(json!({ "device_on": on })),
+    )?;
+    Ok(())
+}
+
+//children of a strip are addressed by wrapping the request for them in control_child
+pub fn set_child_device_on<T: Transport + ?Sized>(
+    transport: &mut T,
+    child_id: &str,
+    on: bool,
+) -> Result<()> {
+    let inner = smart_request("set_device_info", Some(json!({ "device_on": on })));
+    let resp = query_smart(
+        transport,
+        "control_child",
+        Some(json!({
+            "device_id": child_id,
+            "requestData": inner,
+        })),
+    )?;
+    //the child's own reply is nested under responseData
+    if let Some(inner) = resp.get("responseData") {
+        check_smart_resp(inner.clone())?;
+    }
+    Ok(())
+}
+
+//children in the same shape the kasa strips report them, so Device can
+//treat both families alike
+pub fn get_children<T: Transport + ?Sized>(transport: &mut T) -> Result<Vec<KasaChildren>> {
+    let list = get_child_device_list(transport)?;
+    Ok(list
+        .child_device_list
+        .into_iter()
+        .map(|child| KasaChildren {
+            alias: child.alias(),
+            id: child.device_id,
+            state: child.device_on as u8,
+            on_time: child.on_time,
+            next_action: NextAction::None,
+        })
+        .collect())
+}
+
+pub fn toggle_single_relay<T: Transport + ?Sized>(transport: &mut T) -> Result<()> {
+    let on = get_device_info(transport)?.device_on;
+    set_device_on(transport, !on)
+}
+
+pub fn set_relay_by_idx<T: Transport + ?Sized>(
+    transport: &mut T,
+    idx: usize,
+    state: u8,
+) -> Result<()> {
+    let children = get_children(transport)?;
+    let child = child_at(&children, idx)?;
+    set_child_device_on(transport, &child.id, state != 0)
+}
+
+pub fn toggle_relay_by_idx<T: Transport + ?Sized>(transport: &mut T, idx: usize) -> Result<()> {
+    let children = get_children(transport)?;
+    let child = child_at(&children, idx)?;
+    set_child_device_on(transport, &child.id, child.state == 0)
+}
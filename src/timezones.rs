@@ -0,0 +1,129 @@
+//kasa devices don't take a timezone name, they take an index into a fixed
+//table baked into the firmware (the list the app shows). this is that table
+//with each entry mapped to the closest IANA zone.
+// https://github.com/python-kasa/python-kasa/blob/master/kasa/iot/iottimezone.py
+pub const TIMEZONES: [&str; 110] = [
+    "Etc/GMT+12",
+    "Pacific/Samoa",
+    "US/Hawaii",
+    "US/Alaska",
+    "Mexico/BajaNorte",
+    "Etc/GMT+8",
+    "PST8PDT",
+    "US/Arizona",
+    "America/Mazatlan",
+    "MST",
+    "MST7MDT",
+    "Mexico/General",
+    "Etc/GMT+6",
+    "CST6CDT",
+    "America/Monterrey",
+    "Canada/Saskatchewan",
+    "America/Bogota",
+    "Etc/GMT+5",
+    "EST",
+    "America/Indiana/Indianapolis",
+    "America/Caracas",
+    "America/Asuncion",
+    "Etc/GMT+4",
+    "Canada/Atlantic",
+    "America/Cuiaba",
+    "Brazil/West",
+    "America/Santiago",
+    "Canada/Newfoundland",
+    "America/Sao_Paulo",
+    "America/Argentina/Buenos_Aires",
+    "America/Cayenne",
+    "America/Miquelon",
+    "America/Montevideo",
+    "Chile/Continental",
+    "Etc/GMT+2",
+    "Atlantic/Azores",
+    "Atlantic/Cape_Verde",
+    "Africa/Casablanca",
+    "UCT",
+    "GB",
+    "Africa/Monrovia",
+    "Europe/Amsterdam",
+    "Europe/Belgrade",
+    "Europe/Brussels",
+    "Europe/Sarajevo",
+    "Africa/Lagos",
+    "Africa/Windhoek",
+    "Asia/Amman",
+    "Europe/Athens",
+    "Asia/Beirut",
+    "Africa/Cairo",
+    "Asia/Damascus",
+    "EET",
+    "Africa/Harare",
+    "Europe/Helsinki",
+    "Asia/Istanbul",
+    "Asia/Jerusalem",
+    "Europe/Kaliningrad",
+    "Africa/Tripoli",
+    "Asia/Baghdad",
+    "Asia/Kuwait",
+    "Europe/Minsk",
+    "Europe/Moscow",
+    "Africa/Nairobi",
+    "Asia/Tehran",
+    "Asia/Muscat",
+    "Asia/Baku",
+    "Europe/Samara",
+    "Indian/Mauritius",
+    "Asia/Tbilisi",
+    "Asia/Yerevan",
+    "Asia/Kabul",
+    "Asia/Ashgabat",
+    "Asia/Yekaterinburg",
+    "Asia/Karachi",
+    "Asia/Kolkata",
+    "Asia/Colombo",
+    "Asia/Kathmandu",
+    "Asia/Almaty",
+    "Asia/Dhaka",
+    "Asia/Novosibirsk",
+    "Asia/Rangoon",
+    "Asia/Bangkok",
+    "Asia/Krasnoyarsk",
+    "Asia/Chongqing",
+    "Asia/Irkutsk",
+    "Asia/Singapore",
+    "Australia/Perth",
+    "Asia/Taipei",
+    "Asia/Ulaanbaatar",
+    "Asia/Tokyo",
+    "Asia/Seoul",
+    "Asia/Yakutsk",
+    "Australia/Adelaide",
+    "Australia/Darwin",
+    "Australia/Brisbane",
+    "Australia/Canberra",
+    "Pacific/Guam",
+    "Australia/Hobart",
+    "Antarctica/DumontDUrville",
+    "Asia/Magadan",
+    "Asia/Srednekolymsk",
+    "Etc/GMT-11",
+    "Asia/Anadyr",
+    "Pacific/Auckland",
+    "Etc/GMT-12",
+    "Pacific/Fiji",
+    "Etc/GMT-13",
+    "Pacific/Apia",
+    "Etc/GMT-14",
+];
+
+pub fn iana_name(index: u8) -> Option<&'static str> {
+    TIMEZONES.get(index as usize).copied()
+}
+
+//exact name match only, several zones share an offset so there's no
+//sensible fallback here. clock::host_timezone_index does the fuzzy match
+pub fn index_of(name: &str) -> Option<u8> {
+    TIMEZONES
+        .iter()
+        .position(|zone| zone.eq_ignore_ascii_case(name))
+        .map(|index| index as u8)
+}
@@ -0,0 +1,436 @@
+use serde_json::Value;
+use std::io;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs, UdpSocket};
+use std::thread;
+use std::time::Duration;
+
+use crate::codec::HEADER_LEN;
+use crate::error::{KasaError, Result};
+use crate::kasa_protocol::{
+    decrypt, encrypt, read_kasa_frame, read_kasa_resp, send_kasa_cmd, DEFAULT_MAX_FRAME_LEN,
+};
+
+pub const DEFAULT_PORT: u16 = 9999;
+
+//anything that can carry a kasa json request to a device and hand back its reply.
+//implementations own the framing and encryption, so the command functions in
+//kasa_protocol only ever deal in plain json strings
+pub trait Transport {
+    fn send(&mut self, request: &str) -> Result<()>;
+    fn receive(&mut self) -> Result<String>;
+
+    fn query(&mut self, request: &str) -> Result<String> {
+        self.send(request)?;
+        self.receive()
+    }
+
+    //query for requests that are safe to repeat, letting transports that retry
+    //resend them after the request went out but the reply didn't come back
+    fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        self.query(request)
+    }
+
+    //whether a socket or session is currently open
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    //drop any socket or session so the next call starts fresh
+    fn disconnect(&mut self) {}
+}
+
+//the legacy protocol: length prefixed, xor autokey encrypted json on port 9999
+impl Transport for TcpStream {
+    fn send(&mut self, request: &str) -> Result<()> {
+        send_kasa_cmd(self, request)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        Ok(decrypt(&read_kasa_resp(self)?))
+    }
+}
+
+//how long to wait on a device and how hard to try before giving up
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ConnectionConfig {
+    pub connect_timeout: Duration,
+    pub read_timeout: Option<Duration>,
+    pub write_timeout: Option<Duration>,
+    //extra attempts after the first one fails, so 0 means try once
+    pub retries: u32,
+    //wait before the first retry, doubled for each one after
+    pub backoff: Duration,
+    //replies advertising a bigger payload than this are rejected unread
+    pub max_frame_len: usize,
+}
+
+impl Default for ConnectionConfig {
+    fn default() -> ConnectionConfig {
+        ConnectionConfig {
+            connect_timeout: Duration::from_secs(2),
+            read_timeout: Some(Duration::from_secs(5)),
+            write_timeout: Some(Duration::from_secs(5)),
+            retries: 2,
+            backoff: Duration::from_millis(250),
+            max_frame_len: DEFAULT_MAX_FRAME_LEN,
+        }
+    }
+}
+
+impl ConnectionConfig {
+    pub fn backoff_for(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << attempt.min(16))
+    }
+}
+
+//errors worth a fresh connection and another go, as opposed to the device
+//telling us no or sending something we can't parse
+pub fn is_retryable(err: &KasaError) -> bool {
+    matches!(
+        err,
+        KasaError::Io(_) | KasaError::Timeout | KasaError::Truncated { .. }
+    )
+}
+
+//devices close idle connections on their side. the next write on a reused
+//socket still goes through, then the read hits eof (or a reset) before any
+//reply byte. the device never saw that request, so it's safe to reconnect
+//and send it once more whatever the command
+pub(crate) fn is_stale_connection(err: &KasaError) -> bool {
+    match err {
+        KasaError::Truncated {
+            expected: HEADER_LEN,
+            received: 0,
+        } => true,
+        KasaError::Io(err) => matches!(
+            err.kind(),
+            io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe
+        ),
+        _ => false,
+    }
+}
+
+//a failed connect or write never reached the device, so that's always tried
+//again, as is a request sent down a connection the device had already closed.
+//once the request is out a lost reply only gets it resent when it's
+//idempotent, otherwise a rule would be added twice or the device rebooted again
+fn query_with_retries<T: Transport + ?Sized>(
+    transport: &mut T,
+    config: &ConnectionConfig,
+    request: &str,
+    idempotent: bool,
+) -> Result<String> {
+    let mut attempt = 0;
+    loop {
+        //every error drops the socket, so a stale one only gets the one resend
+        let reused = transport.is_connected();
+        let (result, resendable) = match transport.send(request) {
+            Ok(()) => (transport.receive(), idempotent),
+            Err(err) => (Err(err), true),
+        };
+        match result {
+            Err(err) if reused && is_stale_connection(&err) => {}
+            Err(err) if resendable && attempt < config.retries && is_retryable(&err) => {
+                thread::sleep(config.backoff_for(attempt));
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+//accepts "ip" or "ip:port", filling in the kasa port when it's missing
+pub fn resolve_addr(addr: &str) -> Result<SocketAddr> {
+    resolve_addr_with_port(addr, DEFAULT_PORT)
+}
+
+pub fn resolve_addr_with_port(addr: &str, default_port: u16) -> Result<SocketAddr> {
+    let with_port = if addr.contains(':') {
+        addr.to_string()
+    } else {
+        format!("{addr}:{default_port}")
+    };
+    with_port
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| KasaError::InvalidAddress(addr.to_string()))
+}
+
+//a TcpStream that connects on first use, applies the configured timeouts and
+//reconnects with backoff when the device drops the connection
+pub struct TcpTransport {
+    addr: String,
+    config: ConnectionConfig,
+    stream: Option<TcpStream>,
+}
+
+impl TcpTransport {
+    pub fn new(addr: &str, config: ConnectionConfig) -> TcpTransport {
+        TcpTransport {
+            addr: addr.to_string(),
+            config,
+            stream: None,
+        }
+    }
+
+    pub fn config(&self) -> &ConnectionConfig {
+        &self.config
+    }
+
+    fn stream(&mut self) -> Result<&mut TcpStream> {
+        if let Some(ref mut stream) = self.stream {
+            return Ok(stream);
+        }
+        let addr = resolve_addr(&self.addr)?;
+        let stream = TcpStream::connect_timeout(&addr, self.config.connect_timeout)?;
+        stream.set_read_timeout(self.config.read_timeout)?;
+        stream.set_write_timeout(self.config.write_timeout)?;
+        Ok(self.stream.insert(stream))
+    }
+
+    //any failure leaves the socket in an unknown state, so drop it and
+    //let the next call reconnect
+    fn on_result<T>(&mut self, result: Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.disconnect();
+        }
+        result
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, request: &str) -> Result<()> {
+        let result = self.stream().and_then(|stream| stream.send(request));
+        self.on_result(result)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        let max_len = self.config.max_frame_len;
+        let result = self
+            .stream()
+            .and_then(|stream| read_kasa_frame(stream, max_len))
+            .map(|resp| decrypt(&resp));
+        self.on_result(result)
+    }
+
+    fn query(&mut self, request: &str) -> Result<String> {
+        let config = self.config;
+        query_with_retries(self, &config, request, false)
+    }
+
+    fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        let config = self.config;
+        query_with_retries(self, &config, request, true)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn disconnect(&mut self) {
+        self.stream = None;
+    }
+}
+
+//the same xor json sent as single unframed datagrams to udp 9999, the way
+//discovery talks to devices. cheaper than a tcp handshake per poll on low power
+//targets, at the cost of needing our own timeout and retry.
+//a successful send says nothing about delivery, so a dropped datagram looks
+//the same as a lost reply. idempotent commands (getters and set-to-a-value
+//setters) are resent on a timeout, the rest (adding or deleting rules,
+//erasing stats, binding, reboot, reset, set_stainfo) are sent once and a
+//timeout leaves it unknown whether the device acted on them
+pub struct UdpTransport {
+    addr: String,
+    config: ConnectionConfig,
+    socket: Option<UdpSocket>,
+    //top level modules of the request in flight, used to match its reply
+    pending: Vec<String>,
+}
+
+//the modules a request or reply covers, ignoring the child context
+fn modules_of(json: &str) -> Vec<String> {
+    let mut modules: Vec<String> = serde_json::from_str::<Value>(json)
+        .ok()
+        .and_then(|value| value.as_object().map(|obj| obj.keys().cloned().collect()))
+        .unwrap_or_default();
+    modules.retain(|module| module != "context");
+    modules.sort();
+    modules
+}
+
+impl UdpTransport {
+    pub fn new(addr: &str, config: ConnectionConfig) -> UdpTransport {
+        UdpTransport {
+            addr: addr.to_string(),
+            config,
+            socket: None,
+            pending: vec![],
+        }
+    }
+
+    fn socket(&mut self) -> Result<&mut UdpSocket> {
+        if let Some(ref mut socket) = self.socket {
+            return Ok(socket);
+        }
+        let addr = resolve_addr(&self.addr)?;
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        //connecting filters out datagrams from anyone but the device
+        socket.connect(addr)?;
+        socket.set_read_timeout(self.config.read_timeout)?;
+        socket.set_write_timeout(self.config.write_timeout)?;
+        Ok(self.socket.insert(socket))
+    }
+
+    //throw away replies to earlier attempts that arrived after we gave up on them
+    fn drain_stale(&mut self) -> Result<()> {
+        let socket = self.socket()?;
+        socket.set_nonblocking(true)?;
+        let mut buf = [0u8; 1];
+        while socket.recv(&mut buf).is_ok() {}
+        socket.set_nonblocking(false)?;
+        Ok(())
+    }
+}
+
+impl Transport for UdpTransport {
+    fn send(&mut self, request: &str) -> Result<()> {
+        self.drain_stale()?;
+        self.pending = modules_of(request);
+        let datagram = encrypt(request, false);
+        self.socket()?.send(&datagram)?;
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        let mut buf = vec![0u8; self.config.max_frame_len];
+        loop {
+            let len = self.socket()?.recv(&mut buf)?;
+            if len == buf.len() {
+                return Err(KasaError::FrameTooLarge {
+                    len,
+                    max: self.config.max_frame_len,
+                });
+            }
+            let resp = decrypt(&buf[..len]);
+            //a late reply to something else, keep waiting for ours
+            if self.pending.is_empty() || modules_of(&resp) == self.pending {
+                return Ok(resp);
+            }
+        }
+    }
+
+    fn query(&mut self, request: &str) -> Result<String> {
+        let config = self.config;
+        query_with_retries(self, &config, request, false)
+    }
+
+    fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        let config = self.config;
+        query_with_retries(self, &config, request, true)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.socket.is_some()
+    }
+
+    fn disconnect(&mut self) {
+        self.socket = None;
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for &mut T {
+    fn send(&mut self, request: &str) -> Result<()> {
+        (**self).send(request)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        (**self).receive()
+    }
+
+    fn query(&mut self, request: &str) -> Result<String> {
+        (**self).query(request)
+    }
+
+    fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        (**self).query_idempotent(request)
+    }
+
+    fn is_connected(&self) -> bool {
+        (**self).is_connected()
+    }
+
+    fn disconnect(&mut self) {
+        (**self).disconnect()
+    }
+}
+
+impl<T: Transport + ?Sized> Transport for Box<T> {
+    fn send(&mut self, request: &str) -> Result<()> {
+        (**self).send(request)
+    }
+
+    fn receive(&mut self) -> Result<String> {
+        (**self).receive()
+    }
+
+    fn query(&mut self, request: &str) -> Result<String> {
+        (**self).query(request)
+    }
+
+    fn query_idempotent(&mut self, request: &str) -> Result<String> {
+        (**self).query_idempotent(request)
+    }
+
+    fn is_connected(&self) -> bool {
+        (**self).is_connected()
+    }
+
+    fn disconnect(&mut self) {
+        (**self).disconnect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    //answers one request per connection and then hangs up, the way a device
+    //drops an idle connection between two calls
+    fn one_reply_per_connection(connections: usize) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut requests = vec![];
+            for stream in listener.incoming().take(connections) {
+                let mut stream = stream.unwrap();
+                let request =
+                    decrypt(&read_kasa_frame(&mut stream, DEFAULT_MAX_FRAME_LEN).unwrap());
+                send_kasa_cmd(&mut stream, r#"{"system":{"err_code":0}}"#).unwrap();
+                requests.push(request);
+            }
+            requests
+        });
+        (addr, handle)
+    }
+
+    #[test]
+    fn resends_once_on_a_connection_the_device_closed() {
+        let (addr, handle) = one_reply_per_connection(2);
+        let config = ConnectionConfig {
+            retries: 0,
+            ..ConnectionConfig::default()
+        };
+        let mut transport = TcpTransport::new(&addr, config);
+        let sysinfo = r#"{"system":{"get_sysinfo":null}}"#;
+        let alias = r#"{"system":{"set_dev_alias":{"alias":"lamp"}}}"#;
+        transport.query_idempotent(sysinfo).unwrap();
+        //give the hang up time to land before the next write
+        thread::sleep(Duration::from_millis(50));
+        transport.query(alias).unwrap();
+        assert_eq!(handle.join().unwrap(), [sysinfo, alias]);
+    }
+}